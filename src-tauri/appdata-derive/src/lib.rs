@@ -0,0 +1,309 @@
+//! `#[derive(AppData)]` for the entities of the `app` crate.
+//!
+//! The generated impls refer to the storage types through `crate::`, so the derive only works
+//! inside the crate that re-exports them at its root.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, LitBool, LitInt, LitStr, Path};
+
+/// Implements `Entity`, `Versioned` and `StoreInfo` for a struct with named fields, and collects it
+/// for registration by `storage::init`.
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize, JsonSchema, Default, AppData)]
+/// #[appdata(store = "UserProfile", key = id)]
+/// pub struct UserProfile {
+///   pub id: u32,
+///   pub name: String,
+/// }
+/// ```
+///
+/// Attributes of `#[appdata(...)]`:
+/// - `store = "..."`: store id, defaults to the struct name
+/// - `key = field`: field holding the key; its type must implement `AppKey`
+/// - `singleton`: the store holds one record keyed by `SingletonKey`; excludes `key`
+/// - `read_only`: see `StoreInfo::READ_ONLY`
+/// - `version = N`, `migrations = path::to::fn`, `lazy_migration`: see `Versioned`
+/// - `register = false`: leave registration to the caller, e.g. for generic entities
+#[proc_macro_derive(AppData, attributes(appdata))]
+pub fn derive_app_data(input: TokenStream) -> TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  expand(&input)
+    .unwrap_or_else(syn::Error::into_compile_error)
+    .into()
+}
+
+#[derive(Default)]
+struct Args {
+  store: Option<LitStr>,
+  key: Option<Ident>,
+  singleton: bool,
+  read_only: bool,
+  version: Option<LitInt>,
+  migrations: Option<Path>,
+  lazy_migration: bool,
+  register: Option<bool>,
+}
+
+/// A flag given as `name` or `name = true|false`
+fn flag(meta: &syn::meta::ParseNestedMeta) -> syn::Result<bool> {
+  if meta.input.peek(syn::Token![=]) {
+    Ok(meta.value()?.parse::<LitBool>()?.value)
+  } else {
+    Ok(true)
+  }
+}
+
+fn parse_args(input: &DeriveInput) -> syn::Result<Args> {
+  let mut args = Args::default();
+  for attr in input.attrs.iter().filter(|a| a.path().is_ident("appdata")) {
+    attr.parse_nested_meta(|meta| {
+      if meta.path.is_ident("store") {
+        args.store = Some(meta.value()?.parse()?);
+      } else if meta.path.is_ident("key") {
+        args.key = Some(meta.value()?.parse()?);
+      } else if meta.path.is_ident("singleton") {
+        args.singleton = flag(&meta)?;
+      } else if meta.path.is_ident("read_only") {
+        args.read_only = flag(&meta)?;
+      } else if meta.path.is_ident("version") {
+        args.version = Some(meta.value()?.parse()?);
+      } else if meta.path.is_ident("migrations") {
+        args.migrations = Some(meta.value()?.parse()?);
+      } else if meta.path.is_ident("lazy_migration") {
+        args.lazy_migration = flag(&meta)?;
+      } else if meta.path.is_ident("register") {
+        args.register = Some(flag(&meta)?);
+      } else {
+        return Err(meta.error("unknown appdata attribute"));
+      }
+      Ok(())
+    })?;
+  }
+  Ok(args)
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+  let name = &input.ident;
+  let fields = match &input.data {
+    Data::Struct(data) => match &data.fields {
+      Fields::Named(fields) => &fields.named,
+      _ => {
+        return Err(syn::Error::new(
+          name.span(),
+          "AppData can only be derived for structs with named fields",
+        ))
+      }
+    },
+    _ => {
+      return Err(syn::Error::new(
+        name.span(),
+        "AppData can only be derived for structs",
+      ))
+    }
+  };
+  let args = parse_args(input)?;
+  let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+  let store = match &args.store {
+    Some(store) => store.clone(),
+    None => LitStr::new(&name.to_string(), name.span()),
+  };
+
+  let (key_impl, key_fields, assert_key) = match (&args.key, args.singleton) {
+    (Some(key), true) => {
+      return Err(syn::Error::new(
+        key.span(),
+        "a singleton store has no key field",
+      ))
+    }
+    (None, false) => {
+      return Err(syn::Error::new(
+        name.span(),
+        "missing `#[appdata(key = field)]`; use `#[appdata(singleton)]` for a store with a single record",
+      ))
+    }
+    (None, true) => (
+      quote! {
+        type Key = crate::SingletonKey;
+        fn get_key(&self) -> &Self::Key {
+          &crate::SingletonKey
+        }
+        fn set_key(&mut self, _key: &Self::Key) {}
+      },
+      quote! {},
+      quote! {},
+    ),
+    (Some(key), false) => {
+      let field = fields
+        .iter()
+        .find(|f| f.ident.as_ref() == Some(key))
+        .ok_or_else(|| {
+          syn::Error::new(key.span(), format!("no field `{}` in `{}`", key, name))
+        })?;
+      let ty = &field.ty;
+      let key_name = key.to_string();
+      let key_name = key_name.trim_start_matches("r#");
+      // Points the error at the field when its type cannot be a key
+      let assert_key = quote_spanned! {ty.span()=>
+        const _: () = {
+          fn assert_key<K: crate::AppKey>() {}
+          let _ = assert_key::<#ty>;
+        };
+      };
+      (
+        quote! {
+          type Key = #ty;
+          fn get_key(&self) -> &Self::Key {
+            &self.#key
+          }
+          fn set_key(&mut self, key: &Self::Key) {
+            self.#key = ::std::clone::Clone::clone(key);
+          }
+        },
+        quote! {
+          fn key_fields() -> &'static [&'static str] {
+            &[#key_name]
+          }
+        },
+        assert_key,
+      )
+    }
+  };
+
+  let version = args
+    .version
+    .iter()
+    .map(|v| quote! { const VERSION: u32 = #v; });
+  let lazy_migration = args.lazy_migration;
+  let migrations = args.migrations.iter().map(|path| {
+    quote! {
+      fn migrations() -> ::std::vec::Vec<crate::Migration> {
+        #path()
+      }
+    }
+  });
+  let read_only = args.read_only;
+  let register = match (args.register, input.generics.params.is_empty()) {
+    (Some(false), _) => quote! {},
+    (_, true) => quote! { crate::register_appdata!(#name); },
+    (_, false) => {
+      return Err(syn::Error::new(
+        input.generics.span(),
+        "generic entities cannot be registered; add `#[appdata(register = false)]`",
+      ))
+    }
+  };
+
+  Ok(quote! {
+    impl #impl_generics ::reindeer::Entity for #name #ty_generics #where_clause {
+      #key_impl
+      fn store_name() -> &'static str {
+        #store
+      }
+    }
+
+    impl #impl_generics crate::Versioned for #name #ty_generics #where_clause {
+      #(#version)*
+      const LAZY_MIGRATION: bool = #lazy_migration;
+      #(#migrations)*
+    }
+
+    impl #impl_generics crate::StoreInfo for #name #ty_generics #where_clause {
+      const READ_ONLY: bool = #read_only;
+      #key_fields
+    }
+
+    #assert_key
+    #register
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use syn::parse_quote;
+
+  fn error(input: DeriveInput) -> String {
+    expand(&input).unwrap_err().to_string()
+  }
+
+  #[test]
+  fn test_expand() {
+    let tokens = expand(&parse_quote! {
+      #[appdata(store = "Profiles", key = id, read_only, version = 2, migrations = profile_migrations)]
+      struct UserProfile {
+        id: u32,
+        name: String,
+      }
+    })
+    .unwrap()
+    .to_string();
+    assert!(tokens.contains("type Key = u32"));
+    assert!(tokens.contains("\"Profiles\""));
+    assert!(tokens.contains("& [\"id\"]"));
+    assert!(tokens.contains("const READ_ONLY : bool = true"));
+    assert!(tokens.contains("const VERSION : u32 = 2"));
+    assert!(tokens.contains("profile_migrations ()"));
+    assert!(tokens.contains("crate :: register_appdata ! (UserProfile)"));
+
+    let tokens = expand(&parse_quote! {
+      #[appdata(singleton)]
+      struct AppConfig {
+        level: u8,
+      }
+    })
+    .unwrap()
+    .to_string();
+    assert!(tokens.contains("type Key = crate :: SingletonKey"));
+    assert!(tokens.contains("\"AppConfig\""));
+  }
+
+  #[test]
+  fn test_expand_errors() {
+    assert!(error(parse_quote! {
+      #[appdata(key = id)]
+      struct Item<T> { id: u32, value: T }
+    })
+    .starts_with("generic entities cannot be registered"));
+    assert!(expand(&parse_quote! {
+      #[appdata(key = id, register = false)]
+      struct Item<T> { id: u32, value: T }
+    })
+    .is_ok());
+    assert!(error(parse_quote! {
+      struct Item { id: u32 }
+    })
+    .starts_with("missing `#[appdata(key = field)]`"));
+    assert_eq!(
+      error(parse_quote! {
+        #[appdata(key = uid)]
+        struct Item { id: u32 }
+      }),
+      "no field `uid` in `Item`"
+    );
+    assert_eq!(
+      error(parse_quote! {
+        #[appdata(key = id, singleton)]
+        struct Item { id: u32 }
+      }),
+      "a singleton store has no key field"
+    );
+    assert_eq!(
+      error(parse_quote! {
+        #[appdata(keys = id)]
+        struct Item { id: u32 }
+      }),
+      "unknown appdata attribute"
+    );
+    assert_eq!(
+      error(parse_quote! {
+        #[appdata(key = id)]
+        struct Item(u32);
+      }),
+      "AppData can only be derived for structs with named fields"
+    );
+  }
+}
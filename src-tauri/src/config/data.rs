@@ -1,15 +1,37 @@
+use crate::{AppData, AppResult, Migration};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /// Application configuration root structure
-#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, AppData)]
 #[serde(rename_all = "camelCase")]
 #[schemars(title = "Application Configuration")]
+#[appdata(singleton, version = 1, migrations = app_config_migrations)]
 pub struct AppConfig {
   /// Logging system configuration
   pub logging: LoggingConfig,
   /// Feature flags and limitations
   pub features: FeaturesConfig,
+  /// Trash bin settings
+  pub trash: TrashConfig,
+}
+
+/// `AppConfig` before the trash settings were added
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AppConfigV0 {
+  logging: LoggingConfig,
+  features: FeaturesConfig,
+}
+
+fn app_config_migrations() -> Vec<Migration> {
+  vec![Migration::new::<AppConfigV0>(
+    0,
+    |mut value| -> AppResult<_> {
+      value["trash"] = serde_json::to_value(AppConfig::default().trash)?;
+      Ok(value)
+    },
+  )]
 }
 
 /// Logging system configuration
@@ -75,6 +97,20 @@ pub struct FeaturesConfig {
   pub max_concurrent: u8,
 }
 
+/// Trash bin settings
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+#[schemars(title = "Trash Configuration")]
+pub struct TrashConfig {
+  /// Days deleted records stay in the trash
+  #[schemars(
+    title = "Auto-Purge After (Days)",
+    range(max = 3650),
+    example = 30,
+    description = "Deleted records older than this are purged from the trash; 0 keeps them until the trash is emptied"
+  )]
+  pub auto_purge_days: u32,
+}
+
 /// Default configuration values
 impl Default for AppConfig {
   fn default() -> Self {
@@ -87,6 +123,9 @@ impl Default for AppConfig {
         dark_mode: false,
         max_concurrent: 8,
       },
+      trash: TrashConfig {
+        auto_purge_days: 30,
+      },
     }
   }
 }
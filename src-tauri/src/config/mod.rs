@@ -1,57 +1,83 @@
 mod data;
 pub use data::*;
 
-use crate::{AppEntity, RawEntity};
+use crate::{AppEntity, AppError, AppResult, AppSingleton, ChangeEvent, ChangeOperation};
 use aarc::{Arc, AtomicArc, Guard};
 use reindeer::Entity;
 use std::sync::{Mutex, OnceLock};
 
-impl Entity for AppConfig {
-  type Key = u32;
+static CONFIG: OnceLock<AtomicArc<AppConfig>> = OnceLock::new();
 
-  fn store_name() -> &'static str {
-    "AppConfig"
-  }
-  fn get_key(&self) -> &Self::Key {
-    &0
-  }
-  fn set_key(&mut self, _key: &Self::Key) {}
+pub fn init_config() -> AppResult<()> {
+  let config = AppConfig::get_or_default()?;
+  CONFIG.set(AtomicArc::new(config)).map_err(|e| {
+    AppError::internal(format!(
+      "AppConfig already initialized: {:?}",
+      *e.load().unwrap()
+    ))
+  })?;
+  watch(purge_trash);
+  on_change(&get_config());
+  crate::watch_changes(on_store_change);
+  Ok(())
+}
 
-  fn save(&self, db: &reindeer::Db) -> reindeer::Result<()> {
-    unsafe { RawEntity::from_ref(self).save(db)? };
-    set_config(self.clone());
-    Ok(())
+/// Keeps the cached config in sync with every write to the `AppConfig` store, including batch
+/// writes that bypass `Entity::save`.
+fn on_store_change(event: &ChangeEvent) {
+  if event.store_id != AppConfig::store_name() {
+    return;
+  }
+  match (event.operation, &event.value) {
+    (ChangeOperation::Save, Some(value)) => match serde_json::from_value(value.clone()) {
+      Ok(config) => {
+        set_config(config);
+      }
+      Err(e) => log::error!("Failed to apply saved AppConfig: {}", e),
+    },
+    // Reads fall back to the defaults once the record is gone
+    (ChangeOperation::Remove, _) => {
+      set_config(AppConfig::default());
+    }
+    (ChangeOperation::Import, _) => {
+      if let Err(e) = load_config() {
+        log::error!("Failed to reload imported AppConfig: {}", e);
+      }
+    }
+    _ => {}
   }
 }
 
-static CONFIG: OnceLock<AtomicArc<AppConfig>> = OnceLock::new();
+/// Auto-purge age last applied to the trash, so other config changes do not purge it again
+static PURGE_DAYS: Mutex<Option<u32>> = Mutex::new(None);
 
-pub fn init_config() -> Result<(), String> {
-  let config = AppConfig::get_data(&0)?;
-  let config = if let Some(config) = config {
-    config
-  } else {
-    let config = AppConfig::default();
-    unsafe { RawEntity::from_ref(&config).save_and_flush()? };
-    config
-  };
-  CONFIG
-    .set(AtomicArc::new(config))
-    .map_err(|e| format!("AppConfig already initialized: {:?}", *e.load().unwrap()))?;
-  on_change(&get_config());
-  Ok(())
+/// Applies the auto-purge age of the trash, at startup and whenever it changes.
+fn purge_trash(config: &AppConfig) {
+  let days = config.trash.auto_purge_days;
+  if PURGE_DAYS.lock().unwrap().replace(days) == Some(days) {
+    return;
+  }
+  match crate::purge_expired_trash(days) {
+    Ok(0) => {}
+    Ok(purged) => log::info!("Purged {} records from the trash", purged),
+    Err(e) => log::error!("Failed to purge the trash: {}", e),
+  }
 }
 
 pub fn get_config() -> Guard<AppConfig> {
   CONFIG.get().unwrap().load().unwrap()
 }
 
-pub fn load_config() -> Result<Arc<AppConfig>, String> {
-  let config = AppConfig::get_data(&0)?.ok_or("Config not found")?;
-  Ok(set_config(config))
+/// The cached config, or `None` before `init_config`.
+pub fn try_get_config() -> Option<Guard<AppConfig>> {
+  CONFIG.get().and_then(|config| config.load())
+}
+
+pub fn load_config() -> AppResult<Arc<AppConfig>> {
+  Ok(set_config(AppConfig::get_or_default()?))
 }
 
-pub fn save_config(config: &AppConfig) -> Result<(), String> {
+pub fn save_config(config: &AppConfig) -> AppResult<()> {
   config.save_and_flush()
 }
 
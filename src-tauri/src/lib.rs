@@ -34,12 +34,43 @@ pub fn run() {
     .invoke_handler(tauri::generate_handler![
       storage::appdata_cmd_schema_ids,
       storage::appdata_cmd_schemas,
+      storage::appdata_cmd_stores,
+      storage::appdata_cmd_dynamic_stores,
+      storage::appdata_cmd_define_store,
+      storage::appdata_cmd_drop_store,
       storage::appdata_cmd_get_schema,
       storage::appdata_cmd_get_data,
+      storage::appdata_cmd_get_singleton,
+      storage::appdata_cmd_update_singleton,
+      storage::appdata_cmd_reset_singleton,
       storage::appdata_cmd_save_data,
+      storage::appdata_cmd_patch_data,
+      storage::appdata_cmd_bulk_update,
+      storage::appdata_cmd_new_data,
+      storage::appdata_cmd_create_data,
+      storage::appdata_cmd_validate_data,
       storage::appdata_cmd_remove_data,
       storage::appdata_cmd_exists_data,
       storage::appdata_cmd_find_next_available_key,
+      storage::appdata_cmd_list_data,
+      storage::appdata_cmd_query_data,
+      storage::appdata_cmd_batch,
+      storage::appdata_cmd_list_revisions,
+      storage::appdata_cmd_get_revision,
+      storage::appdata_cmd_restore_revision,
+      storage::appdata_cmd_export,
+      storage::appdata_cmd_import,
+      storage::appdata_cmd_preview_import,
+      storage::appdata_cmd_data_formats,
+      storage::appdata_cmd_export_data,
+      storage::appdata_cmd_import_data,
+      storage::appdata_cmd_propose_csv_mapping,
+      storage::appdata_cmd_import_csv,
+      storage::appdata_cmd_list_trash,
+      storage::appdata_cmd_restore_trash,
+      storage::appdata_cmd_purge_trash,
+      storage::appdata_cmd_empty_trash,
+      storage::appdata_cmd_diff_data,
       test::greet,
     ])
     .run(tauri::generate_context!())
@@ -58,6 +89,12 @@ async fn setup<R: tauri::Runtime>(app: &tauri::AppHandle<R>) {
       return;
     }
   };
+  exit_code += 1;
+  if let Err(e) = logger::init(app_data_dir.clone()) {
+    eprintln!("Failed to initialize logger: {}", e);
+    app.exit(exit_code);
+    return;
+  }
   #[cfg(debug_assertions)]
   {
     log::debug!("app_data_dir={}", app_data_dir.display());
@@ -69,41 +106,16 @@ async fn setup<R: tauri::Runtime>(app: &tauri::AppHandle<R>) {
     return;
   }
   exit_code += 1;
-  if let Err(e) = config::init_config() {
-    eprintln!("Failed to initialize config: {}", e);
-    app.exit(exit_code);
-    return;
-  }
-  exit_code += 1;
-  if let Err(e) = logger::init(app_data_dir.clone()) {
-    eprintln!("Failed to initialize logger: {}", e);
+  if let Err(e) = storage::init_events(app.clone()) {
+    eprintln!("Failed to initialize storage events: {}", e);
     app.exit(exit_code);
     return;
   }
   exit_code += 1;
-  if let Err(e) = register_all_appdata().await {
-    log::error!("Failed to register all appdata: {}", e);
+  if let Err(e) = config::init_config() {
+    eprintln!("Failed to initialize config: {}", e);
     app.exit(exit_code);
     return;
   }
   log::info!("Setup complete");
 }
-
-async fn register_all_appdata() -> anyhow::Result<()> {
-  config::AppConfig::register().await?;
-  test::ProductConfig::register().await?;
-  test::SystemSettings::register().await?;
-  test::UserProfile::register().await?;
-  Ok(())
-}
-
-#[cfg(test)]
-mod tests {
-  use super::*;
-
-  #[tokio::test]
-  async fn test_register_all_appdata() {
-    let result = register_all_appdata().await;
-    assert!(result.is_ok());
-  }
-}
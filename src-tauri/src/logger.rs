@@ -17,12 +17,12 @@ pub fn init(app_data_dir: PathBuf) -> Result<(), fern::InitError> {
       ))
     })
     // Global log level: dynamic
-    .filter(|record| record.level() <= config::get_config().logging.level.to_log_level_filter())
+    .filter(|record| record.level() <= logging().level.to_log_level_filter())
     // Console output (available in development environment)
     .chain(std::io::stdout())
     .chain(
       Dispatch::new()
-        .filter(|_record| config::get_config().logging.file_logging)
+        .filter(|_record| logging().file_logging)
         // All logs written to main file
         .chain(fern::log_file(log_dir.join("app.log"))?)
         // Error logs written separately to error.log
@@ -36,6 +36,15 @@ pub fn init(app_data_dir: PathBuf) -> Result<(), fern::InitError> {
   Ok(())
 }
 
+/// Logging settings; the defaults until the config is loaded from storage, so that logs from
+/// opening the stores are not lost.
+fn logging() -> config::LoggingConfig {
+  match config::try_get_config() {
+    Some(config) => config.logging.clone(),
+    None => config::AppConfig::default().logging,
+  }
+}
+
 impl config::LogLevel {
   pub fn to_log_level_filter(&self) -> log::LevelFilter {
     match self {
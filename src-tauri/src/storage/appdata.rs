@@ -1,150 +1,686 @@
-use reindeer::Entity;
+use reindeer::{AsBytes, Entity};
 use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
 use std::fs::File;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::{Arc, LazyLock};
 use tokio::sync::RwLock;
 
-use super::{db, flush_db};
+use super::{
+  allocate_key, check_revision, clear_revisions, current_revision, db, ensure_migrated, flush_db,
+  get_writable, key_value, next_free_key, notify, parse_key, peek_record, store_validator,
+  upgrade_json, violations, write_lock, write_record, AppError, AppKey, AppResult, ChangeEvent,
+  ChangeOperation, EncodedRecord, FieldError, KeyType, Query, QueryResult, RecordWrite,
+  StoreDescriptor, StoreInfo, StoreKind, Versioned,
+};
 
-pub trait AppEntity {
+pub trait AppEntity: Entity<Key: AppKey> {
   fn store() -> &'static str;
-  fn get_data(key: &u32) -> Result<Option<Self>, String>
-  where
-    Self: Sized;
-  fn save_and_flush(&self) -> Result<(), String>;
-  fn remove_and_flush(key: &u32) -> Result<(), String>;
-  fn export_data(f: File) -> Result<(), String>;
-  fn import_and_flush(f: File) -> Result<(), String>;
-  fn exists_data(key: &u32) -> Result<bool, String>;
-  fn find_next_available_key(start_key: u32) -> Result<u32, String>;
-}
-
-impl<T: Entity<Key = u32>> AppEntity for T {
+  fn get_data(key: &Self::Key) -> AppResult<Option<Self>>;
+  fn save_and_flush(&self) -> AppResult<()>;
+  /// Saves unless the stored revision differs from `expected_revision`, returning the new one.
+  fn save_checked(&self, expected_revision: Option<u64>) -> AppResult<u64>;
+  /// Saves as a new record under a freshly allocated key, set with `Entity::set_key`, and returns
+  /// its revision.
+  fn create_and_flush(&mut self) -> AppResult<u64>;
+  fn remove_and_flush(key: &Self::Key) -> AppResult<()>;
+  fn remove_checked(key: &Self::Key, expected_revision: Option<u64>) -> AppResult<()>;
+  fn get_revision(key: &Self::Key) -> AppResult<u64>;
+  fn export_data(f: File) -> AppResult<()>;
+  fn import_and_flush(f: File) -> AppResult<()>;
+  fn exists_data(key: &Self::Key) -> AppResult<bool>;
+  fn get_all_data() -> AppResult<Vec<Self>>;
+  /// First unused key from `start_key` on; fails for stores whose keys are not sequential.
+  ///
+  /// Only a hint, since the key may be taken before it is used: allocate keys with
+  /// `create_and_flush` instead.
+  fn find_next_available_key(start_key: &Self::Key) -> AppResult<Self::Key>;
+  fn list_data(options: &ListOptions) -> AppResult<Page<Self>>;
+}
+
+/// Iteration order of a paginated listing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Direction {
+  #[default]
+  Forward,
+  Backward,
+}
+
+/// Cursor-based pagination options for listing a store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListOptions {
+  /// Inclusive key to start from; `None` starts at the first (or last) record
+  #[serde(default)]
+  pub start_key: Option<Value>,
+  /// Maximum number of records in the page
+  pub limit: usize,
+  #[serde(default)]
+  pub direction: Direction,
+  /// Also count the records of the store, which reads every key in it
+  #[serde(default)]
+  pub with_total: bool,
+}
+
+/// One page of records, in the order of their stored keys
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Page<T> {
+  pub items: Vec<T>,
+  /// Number of records in the store, if `with_total` was set
+  pub total: Option<usize>,
+  /// Key to pass as `start_key` to fetch the next page, if any
+  pub next_key: Option<Value>,
+}
+
+/// A stored record with the revision to send back as `expected_revision`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Record {
+  pub key: Value,
+  pub data: Vec<u8>,
+  pub revision: u64,
+}
+
+impl<T> Page<T> {
+  pub fn try_map<U, E>(self, f: impl FnMut(T) -> Result<U, E>) -> Result<Page<U>, E> {
+    Ok(Page {
+      items: self.items.into_iter().map(f).collect::<Result<_, _>>()?,
+      total: self.total,
+      next_key: self.next_key,
+    })
+  }
+}
+
+impl<T: Entity<Key: AppKey> + Versioned> AppEntity for T {
   fn store() -> &'static str {
     <Self as Entity>::store_name()
   }
-  fn get_data(key: &u32) -> Result<Option<Self>, String>
-  where
-    Self: Sized,
-  {
-    Self::get(key, db()).map_err(|e| e.to_string())
+  fn get_data(key: &Self::Key) -> AppResult<Option<Self>> {
+    ensure_migrated::<Self>()?;
+    Ok(Self::get(key, db())?)
   }
-  fn save_and_flush(&self) -> Result<(), String> {
-    self.save(db()).map_err(|e| e.to_string())?;
-    flush_db()
+  fn save_and_flush(&self) -> AppResult<()> {
+    self.save_checked(None).map(|_| ())
   }
-  fn remove_and_flush(key: &u32) -> Result<(), String> {
-    Self::remove(key, db()).map_err(|e| e.to_string())?;
-    flush_db()
+  fn save_checked(&self, expected_revision: Option<u64>) -> AppResult<u64> {
+    ensure_migrated::<Self>()?;
+    let guard = write_lock();
+    let (revision, event) = save_locked(self, expected_revision)?;
+    drop(guard);
+    notify(event);
+    Ok(revision)
   }
-  fn export_data(f: File) -> Result<(), String> {
-    Self::export_json(f, true, db()).map_err(|e| e.to_string())
+  fn create_and_flush(&mut self) -> AppResult<u64> {
+    ensure_migrated::<Self>()?;
+    let guard = write_lock();
+    self.set_key(&allocate_key::<Self>()?);
+    let (revision, event) = save_locked(self, None)?;
+    drop(guard);
+    notify(event);
+    Ok(revision)
   }
-  fn import_and_flush(f: File) -> Result<(), String> {
-    Self::import_json(f, db()).map_err(|e| e.to_string())?;
-    flush_db()
+  fn remove_and_flush(key: &Self::Key) -> AppResult<()> {
+    Self::remove_checked(key, None)
   }
-  fn exists_data(key: &u32) -> Result<bool, String> {
-    Self::exists(key, db()).map_err(|e| e.to_string())
+  fn remove_checked(key: &Self::Key, expected_revision: Option<u64>) -> AppResult<()> {
+    let key_bytes = key.as_bytes();
+    ensure_migrated::<Self>()?;
+    let guard = write_lock();
+    check_revision(Self::store(), &key_bytes, expected_revision)?;
+    let previous = Self::get(key, db())?
+      .map(|data| serde_json::to_value(&data))
+      .transpose()?;
+    let (_, event) = write_record(RecordWrite {
+      store_id: Self::store(),
+      tree: Self::get_tree(db())?,
+      version: Self::VERSION,
+      key: key_value(key)?,
+      key_bytes,
+      record: None,
+      previous,
+    })?;
+    drop(guard);
+    notify(event);
+    Ok(())
   }
-  fn find_next_available_key(start_key: u32) -> Result<u32, String> {
-    let mut key = start_key;
-    loop {
-      if !Self::exists(&key, db()).map_err(|e| e.to_string())? {
-        return Ok(key);
-      }
-      key = key.checked_add(1).ok_or("Key overflow")?;
-    }
+  fn get_revision(key: &Self::Key) -> AppResult<u64> {
+    current_revision(Self::store(), &key.as_bytes())
+  }
+  fn export_data(f: File) -> AppResult<()> {
+    ensure_migrated::<Self>()?;
+    Ok(Self::export_json(f, true, db())?)
+  }
+  fn import_and_flush(f: File) -> AppResult<()> {
+    ensure_migrated::<Self>()?;
+    let guard = write_lock();
+    Self::import_json(f, db())?;
+    clear_revisions(Self::store())?;
+    flush_db()?;
+    drop(guard);
+    notify(ChangeEvent {
+      store_id: Self::store().to_string(),
+      key: None,
+      operation: ChangeOperation::Import,
+      revision: None,
+      value: None,
+    });
+    Ok(())
+  }
+  fn exists_data(key: &Self::Key) -> AppResult<bool> {
+    Ok(Self::exists(key, db())?)
+  }
+  fn get_all_data() -> AppResult<Vec<Self>> {
+    ensure_migrated::<Self>()?;
+    let mut all = Self::get_all(db())?;
+    all.sort_by(|a, b| a.get_key().cmp(b.get_key()));
+    Ok(all)
+  }
+  fn find_next_available_key(start_key: &Self::Key) -> AppResult<Self::Key> {
+    next_free_key(Self::store(), &Self::get_tree(db())?, start_key.clone())
+  }
+  fn list_data(options: &ListOptions) -> AppResult<Page<Self>> {
+    ensure_migrated::<Self>()?;
+    let tree = Self::get_tree(db())?;
+    paginate(
+      options,
+      || tree.len(),
+      |start: Option<Self::Key>| {
+        Ok(
+          scan_from(&tree, start.map(|key| key.as_bytes()), options.direction).map(|entry| {
+            let data: Self = reindeer::bincode_deserialize(&entry?.1)?;
+            Ok((data.get_key().clone(), data))
+          }),
+        )
+      },
+    )
   }
 }
 
+/// Entries of `tree` from the encoded key `start` on, in `direction` and in the order of the
+/// encoded keys.
+pub(super) fn scan_from(
+  tree: &::sled::Tree,
+  start: Option<Vec<u8>>,
+  direction: Direction,
+) -> Box<dyn Iterator<Item = ::sled::Result<(::sled::IVec, ::sled::IVec)>>> {
+  let entries = match (start, direction) {
+    (None, _) => tree.iter(),
+    (Some(start), Direction::Forward) => tree.range(start..),
+    (Some(start), Direction::Backward) => tree.range(..=start),
+  };
+  match direction {
+    Direction::Forward => Box::new(entries),
+    Direction::Backward => Box::new(entries.rev()),
+  }
+}
+
+/// Cuts one page out of the records `scan` yields from the parsed cursor on, which must come in
+/// listing order; only the page and the record after it are read. `count` gives the store size
+/// and is only called for `with_total`, as it takes a full scan.
+pub(super) fn paginate<K: AppKey, I, S>(
+  options: &ListOptions,
+  count: impl FnOnce() -> usize,
+  scan: impl FnOnce(Option<K>) -> AppResult<S>,
+) -> AppResult<Page<I>>
+where
+  S: Iterator<Item = AppResult<(K, I)>>,
+{
+  let start_key = options.start_key.as_ref().map(parse_key::<K>).transpose()?;
+  let mut items = scan(start_key)?
+    .take(options.limit.saturating_add(1))
+    .collect::<AppResult<Vec<_>>>()?;
+  let next_key = if items.len() > options.limit {
+    items.pop().map(|(key, _)| key_value(&key)).transpose()?
+  } else {
+    None
+  };
+  Ok(Page {
+    items: items.into_iter().map(|(_, data)| data).collect(),
+    total: options.with_total.then(count),
+    next_key,
+  })
+}
+
+/// Saves a record and records its history; the caller must hold the write lock. Returns the new
+/// revision and the event to notify once the lock is released.
+pub(super) fn save_locked<T: AppEntity + Versioned>(
+  data: &T,
+  expected_revision: Option<u64>,
+) -> AppResult<(u64, ChangeEvent)> {
+  let key = data.get_key();
+  let key_bytes = key.as_bytes();
+  check_revision(T::store(), &key_bytes, expected_revision)?;
+  let previous = T::get(key, db())?
+    .map(|data| serde_json::to_value(&data))
+    .transpose()?;
+  write_record(RecordWrite {
+    store_id: T::store(),
+    tree: T::get_tree(db())?,
+    version: T::VERSION,
+    key: key_value(key)?,
+    key_bytes,
+    record: Some((
+      reindeer::bincode_serialize(data)?,
+      serde_json::to_value(data)?,
+    )),
+    previous,
+  })
+}
+
+/// Type-erased access to a store; keys are passed in their JSON form, see `KeyType`.
 pub trait AppData: Sync + Send + 'static {
   fn id(&self) -> &'static str;
+  /// Name of the implementing type, for diagnostics
+  fn type_name(&self) -> &'static str;
+  /// The record schema, with the key type under `x-key-type`
   fn schema(&self) -> schemars::Schema;
-  fn get_data(&self, key: u32) -> Result<Option<Vec<u8>>, String>;
-  fn save_and_flush(&self, data: &[u8]) -> Result<(), String>;
-  fn remove_and_flush(&self, key: u32) -> Result<(), String>;
-  fn exists_data(&self, key: u32) -> Result<bool, String>;
-  fn find_next_available_key(&self, start_key: u32) -> Result<u32, String>;
+  fn key_type(&self) -> KeyType;
+  fn get_data(&self, key: &Value) -> AppResult<Option<Vec<u8>>>;
+  /// The record in JSON form, upgraded in memory; unlike `get_data` it never migrates the store.
+  fn peek_data(&self, key: &Value) -> AppResult<Option<Value>>;
+  /// The record together with its current revision
+  fn get_record(&self, key: &Value) -> AppResult<Option<Record>>;
+  fn validate_data(&self, data: &[u8]) -> AppResult<Vec<FieldError>>;
+  fn save_and_flush(&self, data: &[u8]) -> AppResult<()>;
+  fn save_checked(&self, data: &[u8], expected_revision: Option<u64>) -> AppResult<u64>;
+  /// Saves a new record under a key allocated here; the key in `data` is ignored.
+  fn create_data(&self, data: &[u8]) -> AppResult<Record>;
+  fn remove_and_flush(&self, key: &Value) -> AppResult<()>;
+  fn remove_checked(&self, key: &Value, expected_revision: Option<u64>) -> AppResult<()>;
+  fn exists_data(&self, key: &Value) -> AppResult<bool>;
+  fn find_next_available_key(&self, start_key: &Value) -> AppResult<Value>;
+  fn list_data(&self, options: &ListOptions) -> AppResult<Page<Vec<u8>>>;
+  /// Runs `query` over `all_values`.
+  fn query_data(&self, query: &Query) -> AppResult<QueryResult<Vec<u8>>> {
+    let result = query.apply(self.all_values()?);
+    Ok(QueryResult {
+      items: result
+        .items
+        .iter()
+        .map(serde_json::to_vec)
+        .collect::<Result<_, _>>()?,
+      total: result.total,
+    })
+  }
+  /// The sled tree backing this store, for writes inside a transaction
+  fn tree(&self) -> AppResult<::sled::Tree>;
+  fn encode_key(&self, key: &Value) -> AppResult<Vec<u8>>;
+  /// Validates and encodes a JSON record the way `Entity::save` stores it.
+  fn encode_data(&self, data: &[u8]) -> AppResult<EncodedRecord>;
+  /// Upgrades the stored records to the current schema version if not done yet
+  fn migrate(&self) -> AppResult<()>;
+  /// Current schema version, see `Versioned`
+  fn version(&self) -> u32;
+  /// Upgrades a JSON record written at version `from` to the current version.
+  fn upgrade(&self, from: u32, value: Value) -> AppResult<Value>;
+  /// Every record in key order, in JSON form
+  fn all_values(&self) -> AppResult<Vec<Value>>;
+  /// Picks an unused key for a new record; the caller must hold the write lock until it is saved.
+  fn allocate_key(&self) -> AppResult<Value>;
+  /// Returns `value` with its key replaced by `key`.
+  fn with_key(&self, value: Value, key: &Value) -> AppResult<Value>;
+  fn read_only(&self) -> bool;
+  fn kind(&self) -> StoreKind;
+  /// Defined at runtime by `define_store` rather than compiled in
+  fn dynamic(&self) -> bool;
+  /// JSON form of a record with every field at its default
+  fn default_value(&self) -> AppResult<Value>;
+  /// An unsaved record with every field at its default, at revision `0`; with `allocate_key` its
+  /// key is taken from the store's key sequence, or picked at random for UUID keys.
+  fn new_record(&self, allocate_key: bool) -> AppResult<Record>;
+  fn descriptor(&self) -> AppResult<StoreDescriptor>;
+}
+
+/// The schema of a typed store, compiled once per schema version.
+fn typed_validator<T: AppData>(appdata: &T) -> AppResult<Arc<jsonschema::Validator>> {
+  store_validator(appdata.id(), appdata.version(), || appdata.schema())
 }
 
-impl<T: AppEntity + Entity<Key = u32> + JsonSchema + Sync + Send + 'static> AppData for T {
+/// Parses a JSON record and rejects it unless it matches the schema of its store.
+fn decode_valid<T: AppData + serde::de::DeserializeOwned>(
+  appdata: &T,
+  data: &[u8],
+) -> AppResult<T> {
+  let value: serde_json::Value = serde_json::from_slice(data)?;
+  let validator = typed_validator(appdata)?;
+  let errors = violations(&validator, &value);
+  if !errors.is_empty() {
+    return Err(AppError::validation(errors));
+  }
+  Ok(serde_json::from_value(value)?)
+}
+
+impl<T: AppEntity + Versioned + StoreInfo + JsonSchema + Default + Sync + Send + 'static> AppData
+  for T
+{
   fn id(&self) -> &'static str {
     <T as AppEntity>::store()
   }
 
+  fn type_name(&self) -> &'static str {
+    std::any::type_name::<T>()
+  }
+
   fn schema(&self) -> schemars::Schema {
-    schemars::schema_for!(Self)
+    let mut schema = schemars::schema_for!(Self);
+    schema.insert(
+      "x-key-type".to_owned(),
+      serde_json::to_value(self.key_type()).unwrap_or_default(),
+    );
+    schema
+  }
+
+  fn key_type(&self) -> KeyType {
+    T::Key::key_type()
+  }
+
+  fn get_data(&self, key: &Value) -> AppResult<Option<Vec<u8>>> {
+    <T as AppEntity>::get_data(&parse_key(key)?).and_then(|data_opt| match data_opt {
+      Some(data) => Ok(Some(serde_json::to_vec(&data)?)),
+      None => Ok(None),
+    })
+  }
+
+  fn peek_data(&self, key: &Value) -> AppResult<Option<Value>> {
+    peek_record::<T>(&parse_key::<T::Key>(key)?.as_bytes())
   }
 
-  fn get_data(&self, key: u32) -> Result<Option<Vec<u8>>, String> {
-    <T as AppEntity>::get_data(&key).and_then(|data_opt| match data_opt {
-      Some(data) => Ok(Some(serde_json::to_vec(&data).map_err(|e| e.to_string())?)),
+  fn get_record(&self, key: &Value) -> AppResult<Option<Record>> {
+    let _guard = write_lock();
+    match self.get_data(key)? {
+      Some(data) => Ok(Some(Record {
+        key: key.clone(),
+        data,
+        revision: <T as AppEntity>::get_revision(&parse_key(key)?)?,
+      })),
       None => Ok(None),
+    }
+  }
+
+  fn validate_data(&self, data: &[u8]) -> AppResult<Vec<FieldError>> {
+    let value: serde_json::Value = serde_json::from_slice(data)?;
+    let validator = typed_validator(self)?;
+    Ok(violations(&validator, &value))
+  }
+
+  fn save_and_flush(&self, data: &[u8]) -> AppResult<()> {
+    let data = decode_valid(self, data)?;
+    <T as AppEntity>::save_and_flush(&data)
+  }
+
+  fn save_checked(&self, data: &[u8], expected_revision: Option<u64>) -> AppResult<u64> {
+    let data = decode_valid(self, data)?;
+    <T as AppEntity>::save_checked(&data, expected_revision)
+  }
+
+  fn create_data(&self, data: &[u8]) -> AppResult<Record> {
+    let mut data = decode_valid(self, data)?;
+    let revision = data.create_and_flush()?;
+    Ok(Record {
+      key: key_value(data.get_key())?,
+      data: serde_json::to_vec(&data)?,
+      revision,
     })
   }
 
-  fn save_and_flush(&self, data: &[u8]) -> Result<(), String> {
-    let data: T = serde_json::from_slice(data).map_err(|e| e.to_string())?;
-    <T as AppEntity>::save_and_flush(&data).map_err(|e| e.to_string())
+  fn remove_and_flush(&self, key: &Value) -> AppResult<()> {
+    <T as AppEntity>::remove_and_flush(&parse_key(key)?)
+  }
+
+  fn remove_checked(&self, key: &Value, expected_revision: Option<u64>) -> AppResult<()> {
+    <T as AppEntity>::remove_checked(&parse_key(key)?, expected_revision)
   }
 
-  fn remove_and_flush(&self, key: u32) -> Result<(), String> {
-    <T as AppEntity>::remove_and_flush(&key)
+  fn exists_data(&self, key: &Value) -> AppResult<bool> {
+    <T as AppEntity>::exists_data(&parse_key(key)?)
   }
 
-  fn exists_data(&self, key: u32) -> Result<bool, String> {
-    <T as AppEntity>::exists_data(&key)
+  fn find_next_available_key(&self, start_key: &Value) -> AppResult<Value> {
+    key_value(&<T as AppEntity>::find_next_available_key(&parse_key(
+      start_key,
+    )?)?)
   }
 
-  fn find_next_available_key(&self, start_key: u32) -> Result<u32, String> {
-    <T as AppEntity>::find_next_available_key(start_key)
+  fn list_data(&self, options: &ListOptions) -> AppResult<Page<Vec<u8>>> {
+    <T as AppEntity>::list_data(options)?
+      .try_map(|data| serde_json::to_vec(&data).map_err(AppError::from))
+  }
+
+  fn tree(&self) -> AppResult<::sled::Tree> {
+    Ok(T::get_tree(db())?)
+  }
+
+  fn encode_key(&self, key: &Value) -> AppResult<Vec<u8>> {
+    Ok(parse_key::<T::Key>(key)?.as_bytes())
+  }
+
+  fn encode_data(&self, data: &[u8]) -> AppResult<EncodedRecord> {
+    let data = decode_valid(self, data)?;
+    Ok(EncodedRecord {
+      key: key_value(data.get_key())?,
+      key_bytes: data.get_key().as_bytes(),
+      bytes: reindeer::bincode_serialize(&data)?,
+      value: serde_json::to_value(&data)?,
+    })
+  }
+
+  fn migrate(&self) -> AppResult<()> {
+    ensure_migrated::<T>()
+  }
+
+  fn version(&self) -> u32 {
+    T::VERSION
+  }
+
+  fn upgrade(&self, from: u32, value: Value) -> AppResult<Value> {
+    upgrade_json::<T>(from, value)
+  }
+
+  fn all_values(&self) -> AppResult<Vec<Value>> {
+    Ok(
+      <T as AppEntity>::get_all_data()?
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<_>, _>>()?,
+    )
+  }
+
+  fn allocate_key(&self) -> AppResult<Value> {
+    ensure_migrated::<T>()?;
+    key_value(&allocate_key::<T>()?)
+  }
+
+  fn with_key(&self, value: Value, key: &Value) -> AppResult<Value> {
+    let mut data: T = serde_json::from_value(value)?;
+    data.set_key(&parse_key(key)?);
+    Ok(serde_json::to_value(&data)?)
+  }
+
+  fn read_only(&self) -> bool {
+    T::READ_ONLY
+  }
+
+  fn kind(&self) -> StoreKind {
+    if T::Key::SINGLETON {
+      StoreKind::Singleton
+    } else {
+      StoreKind::Collection
+    }
+  }
+
+  fn dynamic(&self) -> bool {
+    false
+  }
+
+  fn default_value(&self) -> AppResult<Value> {
+    Ok(serde_json::to_value(T::default())?)
+  }
+
+  fn new_record(&self, allocate: bool) -> AppResult<Record> {
+    let mut data = T::default();
+    if allocate {
+      ensure_migrated::<T>()?;
+      // The sequence moves past the key, so it is not handed out again even if never saved
+      let _guard = write_lock();
+      data.set_key(&allocate_key::<T>()?);
+    }
+    Ok(Record {
+      key: key_value(data.get_key())?,
+      data: serde_json::to_vec(&data)?,
+      revision: 0,
+    })
+  }
+
+  fn descriptor(&self) -> AppResult<StoreDescriptor> {
+    let schema = self.schema();
+    let text = |name: &str| schema.get(name).and_then(Value::as_str).map(str::to_owned);
+    Ok(StoreDescriptor {
+      id: self.id().to_owned(),
+      title: text("title").unwrap_or_else(|| self.id().to_owned()),
+      description: text("description"),
+      kind: self.kind(),
+      key_fields: match self.kind() {
+        StoreKind::Singleton => vec![],
+        StoreKind::Collection => T::key_fields().iter().map(|f| f.to_string()).collect(),
+      },
+      key_type: self.key_type(),
+      count: self.tree()?.len(),
+      read_only: T::READ_ONLY,
+    })
   }
 }
 
 static REGISTERED_APPDATA: LazyLock<RwLock<HashMap<String, Arc<dyn AppData>>>> =
   LazyLock::new(|| RwLock::new(HashMap::new()));
 
-pub trait AppDataRegister: AppData + Default {
+pub trait AppDataRegister: AppData + Versioned + Default {
   fn register() -> impl std::future::Future<Output = anyhow::Result<()>> + Send {
     async {
       let appdata = Self::default();
-      let key = appdata.id();
-      let old = REGISTERED_APPDATA
-        .write()
-        .await
-        .insert(key.to_string(), Arc::new(appdata));
-      if let Some(old) = old {
-        return Err(anyhow::anyhow!(
-          "AppData already registered: id={}, type={}, new_type={}",
-          key,
-          std::any::type_name_of_val(old.as_ref()),
-          std::any::type_name::<Self>(),
-        ));
+      if !Self::LAZY_MIGRATION {
+        appdata.migrate()?;
       }
-      println!(
-        "AppData registered: id={}, type={}",
-        key,
-        std::any::type_name::<Self>()
-      );
-      Ok(())
+      insert_appdata(Arc::new(appdata)).await
+    }
+  }
+}
+
+impl<T: AppData + Versioned + Default> AppDataRegister for T {}
+
+/// Adds a store to the registry; fails if its id is taken.
+pub(super) async fn insert_appdata(appdata: Arc<dyn AppData>) -> anyhow::Result<()> {
+  let key = appdata.id();
+  let mut registered = REGISTERED_APPDATA.write().await;
+  if let Some(old) = registered.get(key) {
+    return Err(anyhow::anyhow!(
+      "AppData already registered: id={}, type={}, new_type={}",
+      key,
+      old.type_name(),
+      appdata.type_name(),
+    ));
+  }
+  println!(
+    "AppData registered: id={}, type={}",
+    key,
+    appdata.type_name()
+  );
+  registered.insert(key.to_string(), appdata);
+  Ok(())
+}
+
+/// A store collected at link time and registered by `storage::init`.
+///
+/// `#[derive(AppData)]` submits one for every entity unless given `register = false`; entities
+/// implemented by hand use `register_appdata!`.
+pub struct AppDataRegistration {
+  store: fn() -> &'static str,
+  type_name: fn() -> &'static str,
+  register: fn() -> RegisterFuture,
+}
+
+type RegisterFuture = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>>;
+
+impl AppDataRegistration {
+  pub const fn of<T: AppDataRegister>() -> Self {
+    Self {
+      store: || T::default().id(),
+      type_name: std::any::type_name::<T>,
+      register: || Box::pin(T::register()),
     }
   }
 }
 
-impl<T: AppData + Default> AppDataRegister for T {}
+inventory::collect!(AppDataRegistration);
 
-async fn get(id: &str) -> Option<Arc<dyn AppData>> {
+/// Collects `$ty` for registration by `storage::init`.
+#[macro_export]
+macro_rules! register_appdata {
+  ($ty:ty) => {
+    $crate::inventory::submit! {
+      $crate::AppDataRegistration::of::<$ty>()
+    }
+  };
+}
+
+/// Registers every collected store, in id order. Fails before registering anything if two of them
+/// share an id.
+pub(super) async fn register_collected() -> anyhow::Result<()> {
+  let mut collected = inventory::iter::<AppDataRegistration>
+    .into_iter()
+    .map(|r| ((r.store)(), r))
+    .collect::<Vec<_>>();
+  collected.sort_by_key(|(store, r)| (*store, (r.type_name)()));
+  let duplicates = duplicate_ids(
+    &collected
+      .iter()
+      .map(|(store, r)| (*store, (r.type_name)()))
+      .collect::<Vec<_>>(),
+  );
+  if !duplicates.is_empty() {
+    return Err(anyhow::anyhow!(duplicates.join("; ")));
+  }
+  for (_, registration) in collected {
+    (registration.register)().await?;
+  }
+  Ok(())
+}
+
+/// Describes every pair of neighbouring `(id, type name)` entries that share an id.
+fn duplicate_ids(sorted: &[(&str, &str)]) -> Vec<String> {
+  sorted
+    .windows(2)
+    .filter(|pair| pair[0].0 == pair[1].0)
+    .map(|pair| {
+      format!(
+        "AppData already registered: id={}, type={}, new_type={}",
+        pair[0].0, pair[0].1, pair[1].1,
+      )
+    })
+    .collect()
+}
+
+/// Removes a store from the registry, returning it.
+pub(super) async fn remove_appdata(id: &str) -> Option<Arc<dyn AppData>> {
+  REGISTERED_APPDATA.write().await.remove(id)
+}
+
+pub(super) async fn get(id: &str) -> Option<Arc<dyn AppData>> {
   REGISTERED_APPDATA.read().await.get(id).cloned()
 }
 
-async fn get_ok(id: &str) -> Result<Arc<dyn AppData>, String> {
-  get(id).await.ok_or(format!("AppData not found: {}", id))
+pub(super) async fn get_ok(id: &str) -> AppResult<Arc<dyn AppData>> {
+  get(id)
+    .await
+    .ok_or_else(|| AppError::not_found(format!("AppData not found: {}", id)))
 }
 
 #[tauri::command]
-pub async fn appdata_cmd_schema_ids() -> Result<Vec<String>, String> {
+pub async fn appdata_cmd_schema_ids() -> AppResult<Vec<String>> {
   let mut keys = REGISTERED_APPDATA
     .read()
     .await
@@ -156,7 +692,7 @@ pub async fn appdata_cmd_schema_ids() -> Result<Vec<String>, String> {
 }
 
 #[tauri::command]
-pub async fn appdata_cmd_schemas() -> Result<Vec<schemars::Schema>, String> {
+pub async fn appdata_cmd_schemas() -> AppResult<Vec<schemars::Schema>> {
   let mut schemas = Vec::new();
   for key in appdata_cmd_schema_ids().await? {
     schemas.push(get_ok(&key).await?.schema());
@@ -165,46 +701,261 @@ pub async fn appdata_cmd_schemas() -> Result<Vec<schemars::Schema>, String> {
 }
 
 #[tauri::command]
-pub async fn appdata_cmd_get_schema(schema_id: &str) -> Result<schemars::Schema, String> {
+pub async fn appdata_cmd_get_schema(schema_id: &str) -> AppResult<schemars::Schema> {
   get_ok(schema_id)
     .await
     .and_then(|appdata| Ok(appdata.schema()))
 }
 
 #[tauri::command]
-pub async fn appdata_cmd_get_data(schema_id: &str, key: u32) -> Result<Option<Vec<u8>>, String> {
+pub async fn appdata_cmd_get_data(schema_id: &str, key: Value) -> AppResult<Option<Record>> {
   get_ok(schema_id)
     .await
-    .and_then(|appdata| appdata.get_data(key))
+    .and_then(|appdata| appdata.get_record(&key))
 }
 
+/// Saves a record and returns its new revision. When `expected_revision` is given the save fails
+/// with `CONFLICT` if the record was changed since that revision was read.
 #[tauri::command]
-pub async fn appdata_cmd_save_data(schema_id: &str, data: Vec<u8>) -> Result<(), String> {
-  get_ok(schema_id)
+pub async fn appdata_cmd_save_data(
+  schema_id: &str,
+  data: Vec<u8>,
+  expected_revision: Option<u64>,
+) -> AppResult<u64> {
+  get_writable(schema_id)
     .await
-    .and_then(|appdata| appdata.save_and_flush(&data))
+    .and_then(|appdata| appdata.save_checked(&data, expected_revision))
 }
 
+/// Saves `data` as a new record under a key allocated by the backend and returns it.
 #[tauri::command]
-pub async fn appdata_cmd_remove_data(schema_id: &str, key: u32) -> Result<(), String> {
+pub async fn appdata_cmd_create_data(schema_id: &str, data: Vec<u8>) -> AppResult<Record> {
+  get_writable(schema_id)
+    .await
+    .and_then(|appdata| appdata.create_data(&data))
+}
+
+/// Returns a new, unsaved record built from the type's `Default`, so new records start from the
+/// same values as in Rust. With `allocate_key` the record gets a fresh key from the backend;
+/// without it, the key is the default one.
+#[tauri::command]
+pub async fn appdata_cmd_new_data(
+  schema_id: &str,
+  allocate_key: Option<bool>,
+) -> AppResult<Record> {
+  let allocate_key = allocate_key.unwrap_or(false);
+  let appdata = if allocate_key {
+    get_writable(schema_id).await?
+  } else {
+    get_ok(schema_id).await?
+  };
+  appdata.new_record(allocate_key)
+}
+
+#[tauri::command]
+pub async fn appdata_cmd_validate_data(
+  schema_id: &str,
+  data: Vec<u8>,
+) -> AppResult<Vec<FieldError>> {
   get_ok(schema_id)
     .await
-    .and_then(|appdata| appdata.remove_and_flush(key))
+    .and_then(|appdata| appdata.validate_data(&data))
 }
 
 #[tauri::command]
-pub async fn appdata_cmd_exists_data(schema_id: &str, key: u32) -> Result<bool, String> {
+pub async fn appdata_cmd_remove_data(
+  schema_id: &str,
+  key: Value,
+  expected_revision: Option<u64>,
+) -> AppResult<()> {
+  get_writable(schema_id)
+    .await
+    .and_then(|appdata| appdata.remove_checked(&key, expected_revision))
+}
+
+#[tauri::command]
+pub async fn appdata_cmd_exists_data(schema_id: &str, key: Value) -> AppResult<bool> {
   get_ok(schema_id)
     .await
-    .and_then(|appdata| appdata.exists_data(key))
+    .and_then(|appdata| appdata.exists_data(&key))
 }
 
 #[tauri::command]
 pub async fn appdata_cmd_find_next_available_key(
   schema_id: &str,
-  start_key: u32,
-) -> Result<u32, String> {
+  start_key: Value,
+) -> AppResult<Value> {
   get_ok(schema_id)
     .await
-    .and_then(|appdata| appdata.find_next_available_key(start_key))
+    .and_then(|appdata| appdata.find_next_available_key(&start_key))
+}
+
+#[tauri::command]
+pub async fn appdata_cmd_list_data(
+  schema_id: &str,
+  options: ListOptions,
+) -> AppResult<Page<Vec<u8>>> {
+  get_ok(schema_id)
+    .await
+    .and_then(|appdata| appdata.list_data(&options))
+}
+
+#[tauri::command]
+pub async fn appdata_cmd_query_data(
+  schema_id: &str,
+  query: Query,
+) -> AppResult<QueryResult<Vec<u8>>> {
+  get_ok(schema_id)
+    .await
+    .and_then(|appdata| appdata.query_data(&query))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::storage::{init_test_storage, ErrorCode};
+  use serde_json::json;
+
+  #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema, crate::AppData)]
+  #[appdata(store = "NewDataTestItem", key = id)]
+  struct Item {
+    id: u32,
+    name: String,
+    tags: Vec<String>,
+  }
+
+  impl Default for Item {
+    fn default() -> Self {
+      Self {
+        id: 0,
+        name: "unnamed".to_owned(),
+        tags: vec!["new".to_owned()],
+      }
+    }
+  }
+
+  #[tokio::test]
+  async fn test_new_data() {
+    crate::storage::init_test_storage().await;
+    let record = appdata_cmd_new_data("NewDataTestItem", None).await.unwrap();
+    assert_eq!((record.key.clone(), record.revision), (json!(0), 0));
+    assert_eq!(
+      serde_json::from_slice::<Value>(&record.data).unwrap(),
+      json!({"id": 0, "name": "unnamed", "tags": ["new"]})
+    );
+
+    AppEntity::save_and_flush(&Item {
+      id: 4,
+      ..Item::default()
+    })
+    .unwrap();
+    let first = appdata_cmd_new_data("NewDataTestItem", Some(true))
+      .await
+      .unwrap();
+    assert_eq!(first.key, json!(5));
+    assert_eq!(
+      serde_json::from_slice::<Value>(&first.data).unwrap(),
+      json!({"id": 5, "name": "unnamed", "tags": ["new"]})
+    );
+    // Nothing is saved, but the key is not handed out again
+    assert!(!<Item as AppEntity>::exists_data(&5).unwrap());
+    let second = appdata_cmd_new_data("NewDataTestItem", Some(true))
+      .await
+      .unwrap();
+    assert_eq!(second.key, json!(6));
+  }
+
+  fn list(start_key: Option<Value>, limit: usize, direction: Direction) -> ListOptions {
+    ListOptions {
+      start_key,
+      limit,
+      direction,
+      with_total: true,
+    }
+  }
+
+  /// Pages through `keys` the way `list_data` scans a tree
+  fn page(keys: &[u32], options: &ListOptions) -> AppResult<Page<u32>> {
+    paginate(
+      options,
+      || keys.len(),
+      |start: Option<u32>| {
+        let mut keys = keys.to_vec();
+        if options.direction == Direction::Backward {
+          keys.reverse();
+        }
+        Ok(
+          keys
+            .into_iter()
+            .filter(move |key| match (start, options.direction) {
+              (None, _) => true,
+              (Some(start), Direction::Forward) => *key >= start,
+              (Some(start), Direction::Backward) => *key <= start,
+            })
+            .map(|key| Ok((key, key * 10))),
+        )
+      },
+    )
+  }
+
+  #[test]
+  fn test_paginate() {
+    let keys = [1, 2, 3, 5, 8];
+    let first = page(&keys, &list(None, 2, Direction::Forward)).unwrap();
+    assert_eq!(
+      (first.items, first.total, first.next_key),
+      (vec![10, 20], Some(5), Some(json!(3)))
+    );
+    let uncounted = ListOptions {
+      with_total: false,
+      ..list(None, 2, Direction::Forward)
+    };
+    assert_eq!(page(&keys, &uncounted).unwrap().total, None);
+    let last = page(&keys, &list(Some(json!(5)), 2, Direction::Forward)).unwrap();
+    assert_eq!((last.items, last.next_key), (vec![50, 80], None));
+    let backward = page(&keys, &list(Some(json!(4)), 2, Direction::Backward)).unwrap();
+    assert_eq!(
+      (backward.items, backward.next_key),
+      (vec![30, 20], Some(json!(1)))
+    );
+
+    let empty = page(&[], &list(None, 2, Direction::Forward)).unwrap();
+    assert_eq!(
+      (empty.items, empty.total, empty.next_key),
+      (vec![], Some(0), None)
+    );
+
+    let err = page(&keys, &list(Some(json!("a")), 2, Direction::Forward)).unwrap_err();
+    assert_eq!(err.code, ErrorCode::InvalidArgument);
+  }
+
+  #[test]
+  fn test_duplicate_ids() {
+    assert!(duplicate_ids(&[("A", "a::A"), ("B", "b::B")]).is_empty());
+    assert_eq!(
+      duplicate_ids(&[("A", "a::A"), ("B", "b::B"), ("B", "c::B")]),
+      vec!["AppData already registered: id=B, type=b::B, new_type=c::B"]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_collected_stores() {
+    let mut collected = inventory::iter::<AppDataRegistration>
+      .into_iter()
+      .map(|r| ((r.store)(), (r.type_name)()))
+      .collect::<Vec<_>>();
+    collected.sort();
+    assert!(duplicate_ids(&collected).is_empty());
+
+    init_test_storage().await;
+    for store in [
+      <crate::config::AppConfig as Entity>::store_name(),
+      <crate::test::ProductConfig as Entity>::store_name(),
+      <crate::test::SystemSettings as Entity>::store_name(),
+      <crate::test::UserProfile as Entity>::store_name(),
+    ] {
+      assert!(collected.iter().any(|(id, _)| *id == store), "{}", store);
+      assert!(get(store).await.is_some(), "{}", store);
+    }
+  }
 }
@@ -0,0 +1,447 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use super::{
+  appdata_cmd_schema_ids, apply_locked, create_file, get_ok, get_writable, notify, open_file,
+  write_lock, AppData, AppError, AppResult, BatchOp,
+};
+
+/// Identifies archive files written by `export_archive`
+pub const ARCHIVE_FORMAT: &str = "craft-gui/appdata-archive";
+
+/// A set of exported stores, written as one JSON file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Archive {
+  pub format: String,
+  /// Milliseconds since the Unix epoch
+  pub exported_at: i64,
+  pub stores: BTreeMap<String, ArchivedStore>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedStore {
+  /// Schema version the records were written in, upgraded on import
+  pub version: u32,
+  pub records: Vec<Value>,
+}
+
+/// What to do with an imported record whose key is already taken
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MergeStrategy {
+  /// Keep the existing record
+  #[default]
+  Skip,
+  /// Replace the existing record
+  Overwrite,
+  /// Save the imported record under a newly allocated key
+  Renumber,
+  /// Import nothing
+  Fail,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportAction {
+  Created,
+  Overwritten,
+  Skipped,
+  Renumbered,
+  /// The key is taken and the strategy is `Fail`
+  Conflict,
+  /// The record does not match the schema or could not be upgraded
+  Invalid,
+}
+
+/// Outcome of one record of an import
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordReport {
+  pub store_id: String,
+  /// Position of the record in the archived store
+  pub index: usize,
+  /// Key in the archive; `None` if the record could not be decoded
+  pub key: Option<Value>,
+  pub action: ImportAction,
+  /// Key the record was saved under when renumbered
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub new_key: Option<Value>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+  /// `false` if nothing was written because a record was invalid or conflicting
+  pub applied: bool,
+  pub records: Vec<RecordReport>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreCount {
+  pub store_id: String,
+  pub count: usize,
+}
+
+/// Resolves the stores to work on; an empty selection means every registered store.
+async fn select_stores(store_ids: &[String]) -> AppResult<Vec<Arc<dyn AppData>>> {
+  let ids = if store_ids.is_empty() {
+    appdata_cmd_schema_ids().await?
+  } else {
+    store_ids.to_vec()
+  };
+  let mut appdatas = Vec::with_capacity(ids.len());
+  for id in ids {
+    appdatas.push(get_ok(&id).await?);
+  }
+  Ok(appdatas)
+}
+
+pub async fn export_archive(path: &str, store_ids: &[String]) -> AppResult<Vec<StoreCount>> {
+  let mut archive = Archive {
+    format: ARCHIVE_FORMAT.to_owned(),
+    exported_at: chrono::Utc::now().timestamp_millis(),
+    stores: BTreeMap::new(),
+  };
+  let mut counts = Vec::new();
+  for appdata in select_stores(store_ids).await? {
+    let records = appdata.all_values()?;
+    counts.push(StoreCount {
+      store_id: appdata.id().to_owned(),
+      count: records.len(),
+    });
+    archive.stores.insert(
+      appdata.id().to_owned(),
+      ArchivedStore {
+        version: appdata.version(),
+        records,
+      },
+    );
+  }
+  serde_json::to_writer_pretty(std::io::BufWriter::new(create_file(path)?), &archive)?;
+  Ok(counts)
+}
+
+pub fn read_archive(path: &str) -> AppResult<Archive> {
+  let archive: Archive = serde_json::from_reader(std::io::BufReader::new(open_file(path)?))?;
+  if archive.format != ARCHIVE_FORMAT {
+    return Err(AppError::invalid_argument(format!(
+      "Not an appdata archive: format={}",
+      archive.format
+    )));
+  }
+  Ok(archive)
+}
+
+/// A record ready to be saved, with its report entry
+struct PlannedRecord {
+  appdata: Arc<dyn AppData>,
+  value: Value,
+  report: RecordReport,
+}
+
+/// Imports the selected stores of an archive in one transaction.
+///
+/// Every record is upgraded to the current schema version and validated first; if any record is
+/// invalid, or conflicts under `MergeStrategy::Fail`, nothing is written and the report says why.
+/// Keys repeated within the archive conflict with each other like with existing records. Without a
+/// selection, read-only stores are left out.
+pub async fn import_archive(
+  archive: Archive,
+  store_ids: &[String],
+  strategy: MergeStrategy,
+) -> AppResult<ImportReport> {
+  let mut stores = Vec::new();
+  for (store_id, store) in archive.stores {
+    if store_ids.contains(&store_id) {
+      stores.push((get_writable(&store_id).await?, store));
+    } else if store_ids.is_empty() {
+      // Read-only stores are only an error when selected explicitly
+      let appdata = get_ok(&store_id).await?;
+      if !appdata.read_only() {
+        stores.push((appdata, store));
+      }
+    }
+  }
+
+  let guard = write_lock();
+  let mut applied = true;
+  let mut planned = Vec::new();
+  let mut reports = Vec::new();
+  let mut taken = HashSet::new();
+  let mut renumbered = Vec::new();
+  for (appdata, store) in stores {
+    for (index, value) in store.records.into_iter().enumerate() {
+      let mut report = RecordReport {
+        store_id: appdata.id().to_owned(),
+        index,
+        key: None,
+        action: ImportAction::Created,
+        new_key: None,
+        message: None,
+      };
+      let encoded = appdata
+        .upgrade(store.version, value)
+        .and_then(|value| appdata.encode_data(&serde_json::to_vec(&value)?));
+      let encoded = match encoded {
+        Ok(encoded) => encoded,
+        Err(e) => {
+          applied = false;
+          report.action = ImportAction::Invalid;
+          report.message = Some(e.message);
+          reports.push(report);
+          continue;
+        }
+      };
+      report.key = Some(encoded.key.clone());
+      let slot = (appdata.id(), encoded.key_bytes);
+      let exists = taken.contains(&slot) || appdata.exists_data(&encoded.key)?;
+      if !exists {
+        taken.insert(slot);
+      } else {
+        match strategy {
+          MergeStrategy::Skip => {
+            report.action = ImportAction::Skipped;
+            reports.push(report);
+            continue;
+          }
+          MergeStrategy::Overwrite => report.action = ImportAction::Overwritten,
+          MergeStrategy::Renumber => {
+            // Allocated after all explicit keys are known, so they cannot collide
+            report.action = ImportAction::Renumbered;
+            renumbered.push(planned.len());
+          }
+          MergeStrategy::Fail => {
+            applied = false;
+            report.action = ImportAction::Conflict;
+            reports.push(report);
+            continue;
+          }
+        }
+      }
+      planned.push(PlannedRecord {
+        appdata: appdata.clone(),
+        value: encoded.value,
+        report,
+      });
+    }
+  }
+
+  let mut events = Vec::new();
+  if applied {
+    for i in renumbered {
+      let record = &mut planned[i];
+      let key = loop {
+        let key = record.appdata.allocate_key()?;
+        let slot = (record.appdata.id(), record.appdata.encode_key(&key)?);
+        if taken.insert(slot) {
+          break key;
+        }
+      };
+      record.value = record.appdata.with_key(record.value.take(), &key)?;
+      record.report.new_key = Some(key);
+    }
+    let ops = planned
+      .iter()
+      .map(|record| {
+        Ok(BatchOp::Save {
+          schema_id: record.appdata.id().to_owned(),
+          data: serde_json::to_vec(&record.value)?,
+          expected_revision: None,
+        })
+      })
+      .collect::<AppResult<Vec<_>>>()?;
+    events = apply_locked(
+      ops
+        .iter()
+        .zip(planned.iter().map(|record| record.appdata.clone())),
+    )?;
+  }
+  drop(guard);
+
+  for event in events {
+    notify(event);
+  }
+  reports.extend(planned.into_iter().map(|record| record.report));
+  reports.sort_by(|a, b| (&a.store_id, a.index).cmp(&(&b.store_id, b.index)));
+  Ok(ImportReport {
+    applied,
+    records: reports,
+  })
+}
+
+/// Exports the given stores, or all registered ones if none are given, to one archive file.
+#[tauri::command]
+pub async fn appdata_cmd_export(
+  path: String,
+  store_ids: Vec<String>,
+) -> AppResult<Vec<StoreCount>> {
+  export_archive(&path, &store_ids).await
+}
+
+/// Imports the given stores of an archive, or all of them if none are given.
+#[tauri::command]
+pub async fn appdata_cmd_import(
+  path: String,
+  store_ids: Vec<String>,
+  strategy: MergeStrategy,
+) -> AppResult<ImportReport> {
+  import_archive(read_archive(&path)?, &store_ids, strategy).await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::storage::{init_test_storage, AppEntity, Migration};
+  use schemars::JsonSchema;
+  use serde_json::json;
+
+  #[derive(
+    Debug, Default, Clone, PartialEq, Serialize, Deserialize, JsonSchema, crate::AppData,
+  )]
+  #[appdata(
+    store = "ArchiveTestItem",
+    key = id,
+    version = 1,
+    migrations = item_migrations
+  )]
+  struct Item {
+    id: u32,
+    name: String,
+    tag: String,
+  }
+
+  #[derive(Serialize, Deserialize)]
+  struct ItemV0 {
+    id: u32,
+    name: String,
+  }
+
+  fn item_migrations() -> Vec<Migration> {
+    vec![Migration::new::<ItemV0>(0, |mut value| {
+      value["tag"] = json!("none");
+      Ok(value)
+    })]
+  }
+
+  fn item(id: u32, name: &str) -> Item {
+    Item {
+      id,
+      name: name.to_owned(),
+      tag: "t".to_owned(),
+    }
+  }
+
+  fn archive(version: u32, records: Vec<Value>) -> Archive {
+    Archive {
+      format: ARCHIVE_FORMAT.to_owned(),
+      exported_at: 0,
+      stores: BTreeMap::from([(
+        "ArchiveTestItem".to_owned(),
+        ArchivedStore { version, records },
+      )]),
+    }
+  }
+
+  /// Imports a record over the existing key `base + 1` and a new one under `base + 2`.
+  async fn import(base: u32, strategy: MergeStrategy) -> ImportReport {
+    AppEntity::save_and_flush(&item(base + 1, "old")).unwrap();
+    let records = vec![
+      serde_json::to_value(item(base + 1, "new")).unwrap(),
+      serde_json::to_value(item(base + 2, "new")).unwrap(),
+    ];
+    import_archive(archive(1, records), &[], strategy)
+      .await
+      .unwrap()
+  }
+
+  fn actions(report: &ImportReport) -> Vec<ImportAction> {
+    report.records.iter().map(|record| record.action).collect()
+  }
+
+  fn name(id: u32) -> Option<String> {
+    <Item as AppEntity>::get_data(&id)
+      .unwrap()
+      .map(|item| item.name)
+  }
+
+  #[tokio::test]
+  async fn test_merge_strategies() {
+    init_test_storage().await;
+
+    let report = import(10, MergeStrategy::Skip).await;
+    assert!(report.applied);
+    assert_eq!(
+      actions(&report),
+      vec![ImportAction::Skipped, ImportAction::Created]
+    );
+    assert_eq!(
+      (name(11), name(12)),
+      (Some("old".to_owned()), Some("new".to_owned()))
+    );
+
+    let report = import(20, MergeStrategy::Overwrite).await;
+    assert_eq!(
+      actions(&report),
+      vec![ImportAction::Overwritten, ImportAction::Created]
+    );
+    assert_eq!(name(21), Some("new".to_owned()));
+
+    let report = import(30, MergeStrategy::Renumber).await;
+    assert_eq!(
+      actions(&report),
+      vec![ImportAction::Renumbered, ImportAction::Created]
+    );
+    let new_key = report.records[0].new_key.clone().unwrap();
+    assert_ne!(new_key, json!(31));
+    assert_eq!(name(31), Some("old".to_owned()));
+    assert_eq!(
+      name(serde_json::from_value(new_key).unwrap()),
+      Some("new".to_owned())
+    );
+
+    let report = import(40, MergeStrategy::Fail).await;
+    assert!(!report.applied);
+    assert_eq!(report.records[0].action, ImportAction::Conflict);
+    assert_eq!((name(41), name(42)), (Some("old".to_owned()), None));
+  }
+
+  #[tokio::test]
+  async fn test_upgrade_old_version() {
+    init_test_storage().await;
+    let report = import_archive(
+      archive(0, vec![json!({"id": 51, "name": "v0"})]),
+      &["ArchiveTestItem".to_owned()],
+      MergeStrategy::Fail,
+    )
+    .await
+    .unwrap();
+    assert!(report.applied);
+    assert_eq!(
+      <Item as AppEntity>::get_data(&51).unwrap(),
+      Some(Item {
+        id: 51,
+        name: "v0".to_owned(),
+        tag: "none".to_owned(),
+      })
+    );
+
+    // A record that does not fit the old version is invalid and nothing is written
+    let report = import_archive(
+      archive(0, vec![json!({"id": 52, "name": "v0"}), json!({"id": 53})]),
+      &[],
+      MergeStrategy::Fail,
+    )
+    .await
+    .unwrap();
+    assert!(!report.applied);
+    assert_eq!(report.records[1].action, ImportAction::Invalid);
+    assert_eq!(name(52), None);
+  }
+}
@@ -0,0 +1,224 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::{
+  check_revision, get_writable, notify, write_lock, write_records, AppData, AppError, AppResult,
+  ChangeEvent, RecordWrite,
+};
+
+/// A record encoded exactly as reindeer stores it
+#[derive(Debug, Clone)]
+pub struct EncodedRecord {
+  pub key: Value,
+  pub key_bytes: Vec<u8>,
+  /// bincode bytes written to the tree
+  pub bytes: Vec<u8>,
+  /// JSON form of the decoded record, as `get_data` would return it
+  pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum BatchOp {
+  Save {
+    schema_id: String,
+    data: Vec<u8>,
+    /// Revision the record must still have before the batch, see `appdata_cmd_save_data`
+    #[serde(default)]
+    expected_revision: Option<u64>,
+  },
+  Remove {
+    schema_id: String,
+    key: Value,
+    #[serde(default)]
+    expected_revision: Option<u64>,
+  },
+}
+
+/// Applies every operation in one sled transaction and flushes once.
+///
+/// All records are decoded, validated and checked against their expected revision before anything
+/// is written, so a bad record aborts the whole batch. Expected revisions refer to the state
+/// before the batch.
+pub async fn apply_batch(ops: Vec<BatchOp>) -> AppResult<()> {
+  let mut appdatas = Vec::with_capacity(ops.len());
+  for (i, op) in ops.iter().enumerate() {
+    let schema_id = match op {
+      BatchOp::Save { schema_id, .. } | BatchOp::Remove { schema_id, .. } => schema_id,
+    };
+    appdatas.push(get_writable(schema_id).await.map_err(|e| at_op(i, e))?);
+  }
+
+  let guard = write_lock();
+  let events = apply_locked(ops.iter().zip(appdatas))?;
+  drop(guard);
+
+  for event in events {
+    notify(event);
+  }
+  Ok(())
+}
+
+/// The body of `apply_batch`, for callers already holding the write lock. Returns the events to
+/// notify once the lock is released.
+///
+/// The records, their revisions, history and trash entries are written in one transaction.
+pub(super) fn apply_locked<'a>(
+  ops: impl IntoIterator<Item = (&'a BatchOp, Arc<dyn AppData>)>,
+) -> AppResult<Vec<ChangeEvent>> {
+  let mut writes = Vec::new();
+  // Latest value of each record touched so far, so repeated keys get the right history
+  let mut latest: HashMap<(&'static str, Vec<u8>), Option<Value>> = HashMap::new();
+  for (i, (op, appdata)) in ops.into_iter().enumerate() {
+    let store_id = appdata.id();
+    let (key, key_bytes, record, expected_revision) = match op {
+      BatchOp::Save {
+        data,
+        expected_revision,
+        ..
+      } => {
+        let record = appdata.encode_data(data).map_err(|e| at_op(i, e))?;
+        (
+          record.key,
+          record.key_bytes,
+          Some((record.bytes, record.value)),
+          *expected_revision,
+        )
+      }
+      BatchOp::Remove {
+        key,
+        expected_revision,
+        ..
+      } => (
+        key.clone(),
+        appdata.encode_key(key).map_err(|e| at_op(i, e))?,
+        None,
+        *expected_revision,
+      ),
+    };
+    check_revision(store_id, &key_bytes, expected_revision).map_err(|e| at_op(i, e))?;
+    let previous = match latest.get(&(store_id, key_bytes.clone())) {
+      Some(value) => value.clone(),
+      None => appdata
+        .get_data(&key)?
+        .map(|bytes| serde_json::from_slice(&bytes))
+        .transpose()?,
+    };
+    latest.insert(
+      (store_id, key_bytes.clone()),
+      record.as_ref().map(|(_, value)| value.clone()),
+    );
+    writes.push(RecordWrite {
+      store_id,
+      tree: appdata.tree()?,
+      version: appdata.version(),
+      key,
+      key_bytes,
+      record,
+      previous,
+    });
+  }
+  write_records(writes)
+}
+
+fn at_op(index: usize, mut err: AppError) -> AppError {
+  err.message = format!("Batch operation {} failed: {}", index, err.message);
+  err
+}
+
+#[tauri::command]
+pub async fn appdata_cmd_batch(ops: Vec<BatchOp>) -> AppResult<()> {
+  apply_batch(ops).await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::storage::{
+    init_test_storage, list_revisions, list_trash, AppEntity, ErrorCode, HISTORY_LIMIT,
+  };
+  use reindeer::AsBytes;
+  use schemars::JsonSchema;
+  use serde_json::json;
+
+  #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, JsonSchema, AppData)]
+  #[appdata(store = "BatchTestItem", key = id)]
+  struct Item {
+    id: u32,
+    #[schemars(range(max = 10))]
+    n: u32,
+  }
+
+  fn save(id: u32, n: u32) -> BatchOp {
+    BatchOp::Save {
+      schema_id: "BatchTestItem".to_owned(),
+      data: serde_json::to_vec(&json!({"id": id, "n": n})).unwrap(),
+      expected_revision: None,
+    }
+  }
+
+  fn remove(id: u32) -> BatchOp {
+    BatchOp::Remove {
+      schema_id: "BatchTestItem".to_owned(),
+      key: json!(id),
+      expected_revision: None,
+    }
+  }
+
+  fn history(id: u32) -> Vec<Option<Value>> {
+    list_revisions("BatchTestItem", &id.as_bytes())
+      .unwrap()
+      .into_iter()
+      .map(|revision| revision.value)
+      .collect()
+  }
+
+  #[tokio::test]
+  async fn test_invalid_record_writes_nothing() {
+    init_test_storage().await;
+    let err = apply_batch(vec![save(1, 1), save(2, 11)])
+      .await
+      .unwrap_err();
+    assert_eq!(err.code, ErrorCode::ValidationFailed);
+    assert!(err.message.starts_with("Batch operation 1 failed"));
+    assert!(!<Item as AppEntity>::exists_data(&1).unwrap());
+    assert_eq!(<Item as AppEntity>::get_revision(&1).unwrap(), 0);
+    assert!(history(1).is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_repeated_keys() {
+    init_test_storage().await;
+    apply_batch(vec![save(3, 1), save(3, 2), remove(3), save(3, 4)])
+      .await
+      .unwrap();
+    assert_eq!(
+      <Item as AppEntity>::get_data(&3).unwrap(),
+      Some(Item { id: 3, n: 4 })
+    );
+    assert_ne!(<Item as AppEntity>::get_revision(&3).unwrap(), 0);
+    assert_eq!(
+      history(3),
+      vec![
+        None,
+        Some(json!({"id": 3, "n": 2})),
+        Some(json!({"id": 3, "n": 1})),
+        None
+      ]
+    );
+    let trash = list_trash("BatchTestItem").unwrap();
+    assert_eq!(trash[0].value, json!({"id": 3, "n": 2}));
+
+    apply_batch(
+      (0..HISTORY_LIMIT as u32 + 2)
+        .map(|n| save(4, n % 10))
+        .collect(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(history(4).len(), HISTORY_LIMIT);
+    assert_eq!(history(4).last(), Some(&Some(json!({"id": 4, "n": 1}))));
+  }
+}
@@ -0,0 +1,298 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Read};
+
+use super::{
+  coerce_cell, csv_error, escape_pointer, get_ok, get_writable, io_error, leaf_columns, open_file,
+  set_path, split_csv_version, AppError, AppResult, ErrorCode, FieldError, LeafColumn,
+};
+
+/// Where the values of a CSV column go
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ColumnMapping {
+  pub column: String,
+  /// Dotted path of the property, e.g. `features.dark_mode`; `None` skips the column
+  pub property: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvMapping {
+  pub columns: Vec<ColumnMapping>,
+  /// Properties no column maps to
+  pub unmapped: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RowError {
+  /// Line of the row in the file, counting from 1
+  pub line: u64,
+  pub errors: Vec<FieldError>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvImportReport {
+  pub imported: usize,
+  /// Rows that were not saved
+  pub failed: Vec<RowError>,
+}
+
+/// Lowercase letters and digits only, so `In Stock`, `in_stock` and `InStock` compare equal
+fn normalize(name: &str) -> String {
+  name
+    .chars()
+    .filter(char::is_ascii_alphanumeric)
+    .map(|c| c.to_ascii_lowercase())
+    .collect()
+}
+
+/// Name of `column` compared with headers at matching pass `pass`
+fn label(column: &LeafColumn, pass: usize) -> Option<String> {
+  match pass {
+    0 => Some(column.name()),
+    1 => column.title.map(str::to_owned),
+    _ => column.path.last().cloned(),
+  }
+}
+
+/// Matches each column to a property by dotted path, then by schema title, then by field name.
+/// Each property is used by at most one column.
+pub(super) fn propose_mapping(columns: &[LeafColumn], headers: &[String]) -> CsvMapping {
+  let mut used = HashSet::new();
+  let mut mapping = Vec::with_capacity(headers.len());
+  for header in headers {
+    let wanted = normalize(header);
+    let property = (0..3).find_map(|pass| {
+      columns
+        .iter()
+        .filter(|column| !used.contains(&column.name()))
+        .find(|column| label(column, pass).map(|label| normalize(&label)).as_ref() == Some(&wanted))
+        .map(LeafColumn::name)
+    });
+    if let Some(property) = &property {
+      used.insert(property.clone());
+    }
+    mapping.push(ColumnMapping {
+      column: header.clone(),
+      property,
+    });
+  }
+  CsvMapping {
+    columns: mapping,
+    unmapped: columns
+      .iter()
+      .map(LeafColumn::name)
+      .filter(|property| !used.contains(property))
+      .collect(),
+  }
+}
+
+type CsvReader = csv::Reader<Cursor<Vec<u8>>>;
+
+/// Reads a CSV file, past the version line `export_data` writes, and returns that version.
+fn csv_reader(path: &str) -> AppResult<(Option<u32>, CsvReader)> {
+  let mut text = String::new();
+  open_file(path)?
+    .read_to_string(&mut text)
+    .map_err(io_error)?;
+  let (version, rest) = split_csv_version(&text)?;
+  let reader = csv::Reader::from_reader(Cursor::new(rest.as_bytes().to_vec()));
+  Ok((version, reader))
+}
+
+/// JSON pointer of a dotted property path
+fn pointer(property: &str) -> String {
+  property
+    .split('.')
+    .map(|segment| format!("/{}", escape_pointer(segment)))
+    .collect()
+}
+
+/// Proposes how the columns of a CSV file map to the properties of a store.
+#[tauri::command]
+pub async fn appdata_cmd_propose_csv_mapping(
+  schema_id: &str,
+  path: String,
+) -> AppResult<CsvMapping> {
+  let schema = get_ok(schema_id).await?.schema();
+  let headers: Vec<String> = csv_reader(&path)?
+    .1
+    .headers()
+    .map_err(csv_error)?
+    .iter()
+    .map(str::to_owned)
+    .collect();
+  Ok(propose_mapping(&leaf_columns(schema.as_value()), &headers))
+}
+
+/// Saves each row of a CSV file as a record, converting cells to the types of the mapped
+/// properties.
+///
+/// Rows of a file written at an older schema version are upgraded first. Rows are saved one by
+/// one like `appdata_cmd_save_data`; rows that cannot be converted or fail
+/// validation are reported and skipped. Any other error, e.g. from storage, aborts the import.
+#[tauri::command]
+pub async fn appdata_cmd_import_csv(
+  schema_id: &str,
+  path: String,
+  mapping: Vec<ColumnMapping>,
+) -> AppResult<CsvImportReport> {
+  let appdata = get_writable(schema_id).await?;
+  let schema = appdata.schema();
+  let root = schema.as_value();
+  let columns: HashMap<String, &Value> = leaf_columns(root)
+    .into_iter()
+    .map(|column| (column.name(), column.schema))
+    .collect();
+  let (version, mut reader) = csv_reader(&path)?;
+  let version_line = version.is_some();
+  let version = version.unwrap_or(appdata.version());
+  let headers = reader.headers().map_err(csv_error)?.clone();
+  let mut targets = Vec::with_capacity(headers.len());
+  for header in headers.iter() {
+    let property = mapping
+      .iter()
+      .find(|m| m.column == header)
+      .and_then(|m| m.property.as_deref());
+    targets.push(match property {
+      Some(property) => Some((
+        property,
+        columns
+          .get(property)
+          .copied()
+          .ok_or_else(|| AppError::invalid_argument(format!("Unknown property: {}", property)))?,
+      )),
+      None => None,
+    });
+  }
+
+  let mut report = CsvImportReport::default();
+  for row in reader.records() {
+    let row = row.map_err(csv_error)?;
+    // Counted from the header, below the version line if there is one
+    let line = row.position().map_or(0, |p| p.line()) + u64::from(version_line);
+    let mut record = Value::Object(Default::default());
+    let mut errors = Vec::new();
+    for (target, cell) in targets.iter().zip(row.iter()) {
+      let Some((property, property_schema)) = target else {
+        continue;
+      };
+      match coerce_cell(root, Some(property_schema), cell.trim()) {
+        Ok(Some(value)) => {
+          let path: Vec<String> = property.split('.').map(str::to_owned).collect();
+          set_path(&mut record, &path, value);
+        }
+        Ok(None) => {}
+        Err(message) => errors.push(FieldError {
+          path: pointer(property),
+          message,
+        }),
+      }
+    }
+    if errors.is_empty() {
+      let saved = appdata
+        .upgrade(version, record)
+        .and_then(|record| appdata.save_and_flush(&serde_json::to_vec(&record)?));
+      match saved {
+        Ok(()) => {
+          report.imported += 1;
+          continue;
+        }
+        // A row can only be at fault for what it contains
+        Err(e)
+          if !matches!(
+            e.code,
+            ErrorCode::ValidationFailed | ErrorCode::InvalidArgument
+          ) =>
+        {
+          return Err(e)
+        }
+        Err(e) if e.fields.is_empty() => errors.push(FieldError {
+          path: e.path.unwrap_or_default(),
+          message: e.message,
+        }),
+        Err(e) => errors = e.fields,
+      }
+    }
+    report.failed.push(RowError { line, errors });
+  }
+  Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::storage::{init_test_storage, AppEntity};
+  use crate::test::ProductConfig;
+  use schemars::JsonSchema;
+
+  #[derive(Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema, crate::AppData)]
+  #[appdata(store = "CsvTestItem", key = id)]
+  struct Item {
+    id: u32,
+    #[schemars(length(min = 1))]
+    name: String,
+    n: u32,
+  }
+
+  #[test]
+  fn test_propose_mapping() {
+    let schema = schemars::schema_for!(ProductConfig);
+    let headers = ["Product Name", "ID", "price", "In-Stock", "Notes"].map(str::to_owned);
+    let mapping = propose_mapping(&leaf_columns(schema.as_value()), &headers);
+    let properties: Vec<_> = mapping
+      .columns
+      .iter()
+      .map(|m| m.property.as_deref())
+      .collect();
+    assert_eq!(
+      properties,
+      vec![
+        Some("name"),
+        Some("id"),
+        Some("price"),
+        Some("in_stock"),
+        None
+      ]
+    );
+    assert_eq!(mapping.unmapped, vec!["category"]);
+  }
+
+  #[tokio::test]
+  async fn test_import_csv() {
+    init_test_storage().await;
+    let path = std::env::temp_dir().join(format!("craft-gui-csv-{}.csv", std::process::id()));
+    std::fs::write(&path, "id,name,n\n1,a,1\n2,b,x\n3,,3\n4,d,4\n").unwrap();
+    let mapping = ["id", "name", "n"]
+      .map(|column| ColumnMapping {
+        column: column.to_owned(),
+        property: Some(column.to_owned()),
+      })
+      .to_vec();
+    let report = appdata_cmd_import_csv("CsvTestItem", path.display().to_string(), mapping)
+      .await
+      .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(report.imported, 2);
+    let failed: Vec<_> = report
+      .failed
+      .iter()
+      .map(|row| (row.line, row.errors[0].path.as_str()))
+      .collect();
+    assert_eq!(failed, vec![(3, "/n"), (4, "/name")]);
+    assert_eq!(
+      <Item as AppEntity>::get_data(&4).unwrap(),
+      Some(Item {
+        id: 4,
+        name: "d".to_owned(),
+        n: 4
+      })
+    );
+    assert!(!<Item as AppEntity>::exists_data(&3).unwrap());
+  }
+}
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::{appdata_cmd_schema_ids, get_ok, AppData, AppError, AppResult, KeyType};
+
+/// How a store is used, for the frontend to pick the right editor.
+pub trait StoreInfo {
+  /// Commands may read the store but not write it; backend code still can
+  const READ_ONLY: bool = false;
+  /// Fields of the record that make up its key, in key order; ignored for singleton stores
+  fn key_fields() -> &'static [&'static str] {
+    &["id"]
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StoreKind {
+  /// Keyed by `SingletonKey`, see `AppSingleton`
+  Singleton,
+  Collection,
+}
+
+/// What the frontend needs to know about a registered store
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreDescriptor {
+  pub id: String,
+  /// Schema title, or the id if the schema has none
+  pub title: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub description: Option<String>,
+  pub kind: StoreKind,
+  pub key_fields: Vec<String>,
+  pub key_type: KeyType,
+  /// Number of records
+  pub count: usize,
+  pub read_only: bool,
+}
+
+/// Resolves a store for a command that writes to it.
+pub(super) async fn get_writable(id: &str) -> AppResult<Arc<dyn AppData>> {
+  let appdata = get_ok(id).await?;
+  if appdata.read_only() {
+    return Err(AppError::invalid_argument(format!(
+      "AppData is read-only: {}",
+      id
+    )));
+  }
+  Ok(appdata)
+}
+
+/// Describes every registered store, sorted by id.
+#[tauri::command]
+pub async fn appdata_cmd_stores() -> AppResult<Vec<StoreDescriptor>> {
+  let mut stores = Vec::new();
+  for id in appdata_cmd_schema_ids().await? {
+    stores.push(get_ok(&id).await?.descriptor()?);
+  }
+  Ok(stores)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::storage::{init_test_storage, ErrorCode};
+  use schemars::JsonSchema;
+
+  /// Test settings
+  #[derive(Debug, Default, Serialize, Deserialize, JsonSchema, crate::AppData)]
+  #[appdata(store = "DescriptorTestConfig", singleton, read_only)]
+  struct Config {
+    level: u8,
+  }
+
+  #[derive(Debug, Default, Serialize, Deserialize, JsonSchema, crate::AppData)]
+  #[schemars(title = "Products")]
+  #[appdata(store = "DescriptorTestProduct", key = code)]
+  struct Product {
+    code: String,
+    name: String,
+  }
+
+  #[tokio::test]
+  async fn test_descriptor() {
+    init_test_storage().await;
+    let config = get_ok("DescriptorTestConfig")
+      .await
+      .unwrap()
+      .descriptor()
+      .unwrap();
+    assert_eq!(
+      (config.kind, config.key_fields, config.read_only),
+      (StoreKind::Singleton, vec![], true)
+    );
+    assert_eq!(config.description.as_deref(), Some("Test settings"));
+
+    let product = get_ok("DescriptorTestProduct")
+      .await
+      .unwrap()
+      .descriptor()
+      .unwrap();
+    assert_eq!(
+      (
+        product.kind,
+        product.key_fields,
+        product.key_type,
+        product.read_only
+      ),
+      (
+        StoreKind::Collection,
+        vec!["code".to_owned()],
+        KeyType::String,
+        false
+      )
+    );
+    assert_eq!((product.title.as_str(), product.count), ("Products", 0));
+
+    let err = get_writable("DescriptorTestConfig").await.err().unwrap();
+    assert_eq!(err.code, ErrorCode::InvalidArgument);
+    assert!(get_writable("DescriptorTestProduct").await.is_ok());
+  }
+}
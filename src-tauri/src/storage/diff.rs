@@ -0,0 +1,286 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{get_ok, get_revision, resolve, AppData, AppError, AppResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeKind {
+  Added,
+  Removed,
+  Changed,
+}
+
+/// A value that differs between two versions of a record
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldChange {
+  /// JSON pointer of the value
+  pub path: String,
+  pub kind: ChangeKind,
+  /// The path spelled with schema titles, e.g. `Features / Dark Mode`; see `label_changes`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub label: Option<String>,
+  /// `None` if the value was added
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub before: Option<Value>,
+  /// `None` if the value was removed
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub after: Option<Value>,
+}
+
+/// Lists the leaf values that differ between `before` and `after`, descending into objects and
+/// arrays.
+pub fn diff_values(before: &Value, after: &Value) -> Vec<FieldChange> {
+  let mut changes = Vec::new();
+  diff_at(String::new(), Some(before), Some(after), &mut changes);
+  changes
+}
+
+/// Escapes a property name for use as a JSON pointer segment.
+pub(super) fn escape_pointer(segment: &str) -> String {
+  segment.replace('~', "~0").replace('/', "~1")
+}
+
+fn diff_at(
+  path: String,
+  before: Option<&Value>,
+  after: Option<&Value>,
+  out: &mut Vec<FieldChange>,
+) {
+  match (before, after) {
+    (Some(Value::Object(a)), Some(Value::Object(b))) => {
+      for (name, value) in a {
+        diff_at(
+          format!("{}/{}", path, escape_pointer(name)),
+          Some(value),
+          b.get(name),
+          out,
+        );
+      }
+      for (name, value) in b.iter().filter(|(name, _)| !a.contains_key(*name)) {
+        diff_at(
+          format!("{}/{}", path, escape_pointer(name)),
+          None,
+          Some(value),
+          out,
+        );
+      }
+    }
+    (Some(Value::Array(a)), Some(Value::Array(b))) => {
+      for i in 0..a.len().max(b.len()) {
+        diff_at(format!("{}/{}", path, i), a.get(i), b.get(i), out);
+      }
+    }
+    (before, after) if before != after => out.push(FieldChange {
+      path,
+      kind: match (before, after) {
+        (None, _) => ChangeKind::Added,
+        (_, None) => ChangeKind::Removed,
+        _ => ChangeKind::Changed,
+      },
+      label: None,
+      before: before.cloned(),
+      after: after.cloned(),
+    }),
+    _ => {}
+  }
+}
+
+/// Labels each change with its path spelled out in the titles of the store schema `root`.
+pub fn label_changes(root: &Value, changes: &mut [FieldChange]) {
+  for change in changes {
+    change.label = field_label(root, &change.path);
+  }
+}
+
+/// Joins the titles of the properties along `path`, falling back to the property name, with
+/// array indices appended as `[i]`. `None` for the whole record.
+fn field_label(root: &Value, path: &str) -> Option<String> {
+  let mut parts: Vec<String> = Vec::new();
+  let mut schema = Some(root);
+  for segment in path.split('/').skip(1) {
+    let name = segment.replace("~1", "/").replace("~0", "~");
+    let resolved = schema.map(|schema| resolve(root, schema));
+    match (name.parse::<usize>(), parts.last_mut()) {
+      (Ok(index), Some(last)) if resolved.is_none_or(|s| s.get("properties").is_none()) => {
+        last.push_str(&format!("[{}]", index));
+        schema = resolved
+          .and_then(|s| s.get("items"))
+          .filter(|s| s.is_object());
+      }
+      _ => {
+        schema = resolved.and_then(|s| {
+          s.get("properties")
+            .and_then(|properties| properties.get(&name))
+            .or_else(|| s.get("additionalProperties").filter(|s| s.is_object()))
+        });
+        let title = schema
+          .and_then(|s| s.get("title").or_else(|| resolve(root, s).get("title")))
+          .and_then(Value::as_str);
+        parts.push(title.map_or(name, str::to_owned));
+      }
+    }
+  }
+  (!parts.is_empty()).then(|| parts.join(" / "))
+}
+
+/// One side of `appdata_cmd_diff_data`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+  tag = "kind",
+  rename_all = "camelCase",
+  rename_all_fields = "camelCase"
+)]
+pub enum DiffSide {
+  /// The stored record
+  Record { key: Value },
+  /// A past state of the record, see `appdata_cmd_list_revisions`
+  Revision { key: Value, revision_id: u64 },
+  /// A record that has not been saved, e.g. the contents of an edit form
+  Payload { data: Vec<u8> },
+}
+
+/// The JSON form of `side`; `None` for a revision taken before the record existed.
+fn side_value(appdata: &dyn AppData, side: DiffSide) -> AppResult<Option<Value>> {
+  match side {
+    DiffSide::Record { key } => match appdata.get_data(&key)? {
+      Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+      None => Err(AppError::not_found(format!(
+        "Data not found: id={}, key={}",
+        appdata.id(),
+        key
+      ))),
+    },
+    DiffSide::Revision { key, revision_id } => {
+      get_revision(appdata.id(), &appdata.encode_key(&key)?, revision_id)?
+        .map(|revision| revision.value)
+        .ok_or_else(|| {
+          AppError::not_found(format!(
+            "Revision not found: id={}, key={}, revision={}",
+            appdata.id(),
+            key,
+            revision_id
+          ))
+        })
+    }
+    // Normalized the way it would be saved, so defaulted fields do not show up as changes; a
+    // payload that would be rejected is compared as sent
+    DiffSide::Payload { data } => match appdata.encode_data(&data) {
+      Ok(encoded) => Ok(Some(encoded.value)),
+      Err(_) => Ok(Some(serde_json::from_slice(&data)?)),
+    },
+  }
+}
+
+/// Lists the values that differ between two records, a record and an unsaved payload, or a record
+/// and one of its revisions, labelled with the schema titles. A side that did not exist yields a
+/// single change of the whole record.
+#[tauri::command]
+pub async fn appdata_cmd_diff_data(
+  schema_id: &str,
+  before: DiffSide,
+  after: DiffSide,
+) -> AppResult<Vec<FieldChange>> {
+  let appdata = get_ok(schema_id).await?;
+  let before = side_value(appdata.as_ref(), before)?;
+  let after = side_value(appdata.as_ref(), after)?;
+  let mut changes = Vec::new();
+  diff_at(String::new(), before.as_ref(), after.as_ref(), &mut changes);
+  label_changes(appdata.schema().as_value(), &mut changes);
+  Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn test_diff_values() {
+    let before = json!({"name": "a", "tags": ["x", "y"], "meta": {"a/b": 1}, "gone": true});
+    let after = json!({"name": "b", "tags": ["x"], "meta": {"a/b": 1}, "new": null});
+    assert_eq!(
+      diff_values(&before, &after),
+      vec![
+        FieldChange {
+          path: "/gone".to_owned(),
+          kind: ChangeKind::Removed,
+          label: None,
+          before: Some(json!(true)),
+          after: None,
+        },
+        FieldChange {
+          path: "/name".to_owned(),
+          kind: ChangeKind::Changed,
+          label: None,
+          before: Some(json!("a")),
+          after: Some(json!("b")),
+        },
+        FieldChange {
+          path: "/tags/1".to_owned(),
+          kind: ChangeKind::Removed,
+          label: None,
+          before: Some(json!("y")),
+          after: None,
+        },
+        FieldChange {
+          path: "/new".to_owned(),
+          kind: ChangeKind::Added,
+          label: None,
+          before: None,
+          after: Some(json!(null)),
+        },
+      ]
+    );
+    assert!(diff_values(&before, &before).is_empty());
+  }
+
+  #[test]
+  fn test_label_changes() {
+    let schema = json!({
+      "type": "object",
+      "properties": {
+        "name": {"type": "string", "title": "Full Name"},
+        "tags": {"type": "array", "items": {"$ref": "#/$defs/Tag"}},
+        "features": {"$ref": "#/$defs/Features"},
+        "extra": {"type": "object", "additionalProperties": {"type": "integer", "title": "Count"}}
+      },
+      "$defs": {
+        "Tag": {"type": "object", "properties": {"label": {"type": "string", "title": "Label"}}},
+        "Features": {
+          "type": "object",
+          "title": "Feature Flags",
+          "properties": {"dark_mode": {"type": "boolean", "title": "Dark Mode"}}
+        }
+      }
+    });
+    let before = json!({
+      "name": "a",
+      "tags": [{"label": "x"}],
+      "features": {"dark_mode": false},
+      "extra": {}
+    });
+    let after = json!({
+      "name": "b",
+      "tags": [{"label": "y"}],
+      "features": {"dark_mode": true},
+      "extra": {"a/b": 1},
+      "other": 2
+    });
+    let mut changes = diff_values(&before, &after);
+    label_changes(&schema, &mut changes);
+    let labels: Vec<_> = changes.iter().map(|c| c.label.as_deref()).collect();
+    assert_eq!(
+      labels,
+      vec![
+        Some("extra / Count"),
+        Some("Feature Flags / Dark Mode"),
+        Some("Full Name"),
+        Some("tags[0] / Label"),
+        Some("other"),
+      ]
+    );
+    assert_eq!(field_label(&schema, ""), None);
+  }
+}
@@ -0,0 +1,697 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use super::{
+  allocate_key_in, check_revision, clear_revisions, clear_sequence, compile_schema,
+  current_revision, db, drop_trash, escape_pointer, flush_db, get, history_tree, insert_appdata,
+  key_value, next_free_key, notify, paginate, parse_key, remove_appdata, scan_from, violations,
+  write_lock, write_record, AppData, AppError, AppKey, AppResult, ChangeEvent, ChangeOperation,
+  EncodedRecord, ErrorCode, FieldError, KeyType, ListOptions, Page, Record, RecordWrite,
+  StoreDescriptor, StoreKind, UuidKey,
+};
+
+/// A store defined at runtime by a JSON Schema instead of a Rust type; its records are stored as
+/// JSON.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DynamicStoreDef {
+  pub id: String,
+  /// JSON Schema every record must match
+  pub schema: Value,
+  /// Record field holding the key
+  #[serde(default = "default_key_field")]
+  pub key_field: String,
+  /// Integer, string or UUID; composite keys are not supported
+  #[serde(default = "default_key_type")]
+  pub key_type: KeyType,
+}
+
+fn default_key_field() -> String {
+  "id".to_owned()
+}
+
+fn default_key_type() -> KeyType {
+  KeyType::Integer
+}
+
+fn defs_tree() -> AppResult<::sled::Tree> {
+  Ok(db().open_tree("__dynamic_stores")?)
+}
+
+struct DynamicStore<K> {
+  id: &'static str,
+  schema: schemars::Schema,
+  /// `schema`, compiled when the store is opened
+  validator: jsonschema::Validator,
+  key_field: String,
+  _key: PhantomData<fn() -> K>,
+}
+
+impl<K: AppKey> DynamicStore<K> {
+  fn object<'a>(&self, value: &'a mut Value) -> AppResult<&'a mut Map<String, Value>> {
+    value
+      .as_object_mut()
+      .ok_or_else(|| AppError::invalid_argument("A record must be a JSON object"))
+  }
+
+  fn key_of(&self, value: &Value) -> AppResult<K> {
+    match value.get(&self.key_field) {
+      Some(key) => parse_key(key),
+      None => Err(AppError::validation(vec![FieldError {
+        path: format!("/{}", escape_pointer(&self.key_field)),
+        message: format!("Missing key field \"{}\"", self.key_field),
+      }])),
+    }
+  }
+
+  /// Parses a JSON record and rejects it unless it matches the schema.
+  fn decode(&self, data: &[u8]) -> AppResult<(K, Value)> {
+    let value: Value = serde_json::from_slice(data)?;
+    let errors = violations(&self.validator, &value);
+    if !errors.is_empty() {
+      return Err(AppError::validation(errors));
+    }
+    Ok((self.key_of(&value)?, value))
+  }
+
+  fn read(&self, key: &K) -> AppResult<Option<Value>> {
+    self
+      .tree()?
+      .get(key.as_bytes())?
+      .map(|bytes| serde_json::from_slice(&bytes).map_err(AppError::from))
+      .transpose()
+  }
+
+  /// Every record with its key, in key order
+  fn entries(&self) -> AppResult<Vec<(K, Value)>> {
+    let mut all = Vec::new();
+    for entry in self.tree()?.iter() {
+      let (key, bytes) = entry?;
+      all.push((K::from_bytes(&key), serde_json::from_slice(&bytes)?));
+    }
+    all.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(all)
+  }
+
+  /// Saves a record and records its history; the caller must hold the write lock. Returns the new
+  /// revision and the event to notify once the lock is released.
+  fn save_locked(
+    &self,
+    key: &K,
+    value: &Value,
+    expected_revision: Option<u64>,
+  ) -> AppResult<(u64, ChangeEvent)> {
+    let key_bytes = key.as_bytes();
+    check_revision(self.id, &key_bytes, expected_revision)?;
+    write_record(RecordWrite {
+      store_id: self.id,
+      tree: self.tree()?,
+      version: 0,
+      key: key_value(key)?,
+      key_bytes,
+      record: Some((serde_json::to_vec(value)?, value.clone())),
+      previous: self.read(key)?,
+    })
+  }
+}
+
+impl<K: AppKey> AppData for DynamicStore<K> {
+  fn id(&self) -> &'static str {
+    self.id
+  }
+
+  fn type_name(&self) -> &'static str {
+    std::any::type_name::<Self>()
+  }
+
+  fn schema(&self) -> schemars::Schema {
+    let mut schema = self.schema.clone();
+    schema.insert(
+      "x-key-type".to_owned(),
+      serde_json::to_value(self.key_type()).unwrap_or_default(),
+    );
+    schema
+  }
+
+  fn key_type(&self) -> KeyType {
+    K::key_type()
+  }
+
+  fn get_data(&self, key: &Value) -> AppResult<Option<Vec<u8>>> {
+    self
+      .read(&parse_key(key)?)?
+      .map(|value| serde_json::to_vec(&value).map_err(AppError::from))
+      .transpose()
+  }
+
+  /// Records are stored as JSON and never migrated, so this is `get_data` in JSON form.
+  fn peek_data(&self, key: &Value) -> AppResult<Option<Value>> {
+    self.read(&parse_key(key)?)
+  }
+
+  fn get_record(&self, key: &Value) -> AppResult<Option<Record>> {
+    let _guard = write_lock();
+    match self.get_data(key)? {
+      Some(data) => Ok(Some(Record {
+        key: key.clone(),
+        data,
+        revision: current_revision(self.id, &self.encode_key(key)?)?,
+      })),
+      None => Ok(None),
+    }
+  }
+
+  fn validate_data(&self, data: &[u8]) -> AppResult<Vec<FieldError>> {
+    let value: Value = serde_json::from_slice(data)?;
+    Ok(violations(&self.validator, &value))
+  }
+
+  fn save_and_flush(&self, data: &[u8]) -> AppResult<()> {
+    self.save_checked(data, None).map(|_| ())
+  }
+
+  fn save_checked(&self, data: &[u8], expected_revision: Option<u64>) -> AppResult<u64> {
+    let (key, value) = self.decode(data)?;
+    let guard = write_lock();
+    let (revision, event) = self.save_locked(&key, &value, expected_revision)?;
+    drop(guard);
+    notify(event);
+    Ok(revision)
+  }
+
+  fn create_data(&self, data: &[u8]) -> AppResult<Record> {
+    let mut value: Value = serde_json::from_slice(data)?;
+    let guard = write_lock();
+    let key = allocate_key_in::<K>(self.id, &self.tree()?)?;
+    // The key is set before validating, since the schema may require it
+    self
+      .object(&mut value)?
+      .insert(self.key_field.clone(), key_value(&key)?);
+    let (key, value) = self.decode(&serde_json::to_vec(&value)?)?;
+    let (revision, event) = self.save_locked(&key, &value, None)?;
+    drop(guard);
+    notify(event);
+    Ok(Record {
+      key: key_value(&key)?,
+      data: serde_json::to_vec(&value)?,
+      revision,
+    })
+  }
+
+  fn remove_and_flush(&self, key: &Value) -> AppResult<()> {
+    self.remove_checked(key, None)
+  }
+
+  fn remove_checked(&self, key: &Value, expected_revision: Option<u64>) -> AppResult<()> {
+    let key: K = parse_key(key)?;
+    let key_bytes = key.as_bytes();
+    let guard = write_lock();
+    check_revision(self.id, &key_bytes, expected_revision)?;
+    let (_, event) = write_record(RecordWrite {
+      store_id: self.id,
+      tree: self.tree()?,
+      version: 0,
+      key: key_value(&key)?,
+      key_bytes,
+      record: None,
+      previous: self.read(&key)?,
+    })?;
+    drop(guard);
+    notify(event);
+    Ok(())
+  }
+
+  fn exists_data(&self, key: &Value) -> AppResult<bool> {
+    Ok(self.tree()?.contains_key(self.encode_key(key)?)?)
+  }
+
+  fn find_next_available_key(&self, start_key: &Value) -> AppResult<Value> {
+    key_value(&next_free_key::<K>(
+      self.id,
+      &self.tree()?,
+      parse_key(start_key)?,
+    )?)
+  }
+
+  fn list_data(&self, options: &ListOptions) -> AppResult<Page<Vec<u8>>> {
+    let tree = self.tree()?;
+    paginate(
+      options,
+      || tree.len(),
+      |start: Option<K>| {
+        Ok(
+          scan_from(&tree, start.map(|key| key.as_bytes()), options.direction).map(|entry| {
+            let (key, bytes) = entry?;
+            Ok((K::from_bytes(&key), bytes.to_vec()))
+          }),
+        )
+      },
+    )
+  }
+
+  /// Fails once the store is dropped, so a caller still holding it cannot recreate the tree.
+  fn tree(&self) -> AppResult<::sled::Tree> {
+    if !defs_tree()?.contains_key(self.id)? {
+      return Err(AppError::not_found(format!(
+        "AppData not found: {}",
+        self.id
+      )));
+    }
+    Ok(db().open_tree(self.id)?)
+  }
+
+  fn encode_key(&self, key: &Value) -> AppResult<Vec<u8>> {
+    Ok(parse_key::<K>(key)?.as_bytes())
+  }
+
+  fn encode_data(&self, data: &[u8]) -> AppResult<EncodedRecord> {
+    let (key, value) = self.decode(data)?;
+    Ok(EncodedRecord {
+      key: key_value(&key)?,
+      key_bytes: key.as_bytes(),
+      bytes: serde_json::to_vec(&value)?,
+      value,
+    })
+  }
+
+  fn migrate(&self) -> AppResult<()> {
+    Ok(())
+  }
+
+  fn version(&self) -> u32 {
+    0
+  }
+
+  fn upgrade(&self, from: u32, value: Value) -> AppResult<Value> {
+    if from != 0 {
+      return Err(AppError::invalid_argument(format!(
+        "Records were written by a newer version: store={}, version={}, supported version=0",
+        self.id, from
+      )));
+    }
+    Ok(value)
+  }
+
+  fn all_values(&self) -> AppResult<Vec<Value>> {
+    Ok(
+      self
+        .entries()?
+        .into_iter()
+        .map(|(_, value)| value)
+        .collect(),
+    )
+  }
+
+  fn allocate_key(&self) -> AppResult<Value> {
+    key_value(&allocate_key_in::<K>(self.id, &self.tree()?)?)
+  }
+
+  fn with_key(&self, mut value: Value, key: &Value) -> AppResult<Value> {
+    let key: K = parse_key(key)?;
+    self
+      .object(&mut value)?
+      .insert(self.key_field.clone(), key_value(&key)?);
+    Ok(value)
+  }
+
+  fn read_only(&self) -> bool {
+    false
+  }
+
+  fn kind(&self) -> StoreKind {
+    StoreKind::Collection
+  }
+
+  fn dynamic(&self) -> bool {
+    true
+  }
+
+  /// The schema's `default`, or else an object of the `default`s of its properties
+  fn default_value(&self) -> AppResult<Value> {
+    if let Some(default) = self.schema.get("default") {
+      return Ok(default.clone());
+    }
+    let mut value = Map::new();
+    if let Some(properties) = self.schema.get("properties").and_then(Value::as_object) {
+      for (name, property) in properties {
+        if let Some(default) = property.get("default") {
+          value.insert(name.clone(), default.clone());
+        }
+      }
+    }
+    Ok(Value::Object(value))
+  }
+
+  fn new_record(&self, allocate: bool) -> AppResult<Record> {
+    let mut value = self.default_value()?;
+    if allocate {
+      let _guard = write_lock();
+      let key = self.allocate_key()?;
+      value = self.with_key(value, &key)?;
+    }
+    Ok(Record {
+      key: value.get(&self.key_field).cloned().unwrap_or_default(),
+      data: serde_json::to_vec(&value)?,
+      revision: 0,
+    })
+  }
+
+  fn descriptor(&self) -> AppResult<StoreDescriptor> {
+    let text = |name: &str| {
+      self
+        .schema
+        .get(name)
+        .and_then(Value::as_str)
+        .map(str::to_owned)
+    };
+    Ok(StoreDescriptor {
+      id: self.id.to_owned(),
+      title: text("title").unwrap_or_else(|| self.id.to_owned()),
+      description: text("description"),
+      kind: StoreKind::Collection,
+      key_fields: vec![self.key_field.clone()],
+      key_type: self.key_type(),
+      count: self.tree()?.len(),
+      read_only: false,
+    })
+  }
+}
+
+fn build<K: AppKey>(
+  def: &DynamicStoreDef,
+  schema: schemars::Schema,
+  validator: jsonschema::Validator,
+) -> Arc<dyn AppData> {
+  let store = DynamicStore::<K> {
+    // Registered stores live for the rest of the process
+    id: Box::leak(def.id.clone().into_boxed_str()),
+    schema,
+    validator,
+    key_field: def.key_field.clone(),
+    _key: PhantomData,
+  };
+  Arc::new(store)
+}
+
+/// Checks a definition and builds its store.
+fn open(def: &DynamicStoreDef) -> AppResult<Arc<dyn AppData>> {
+  // The id goes into change event names, which Tauri limits to a few characters, and `/`
+  // separates it from the key there
+  if def.id.is_empty()
+    || def.id.starts_with("__")
+    || !def
+      .id
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+  {
+    return Err(AppError::invalid_argument(format!(
+      "Invalid store id: \"{}\"",
+      def.id
+    )));
+  }
+  if def.key_field.is_empty() {
+    return Err(AppError::invalid_argument(
+      "The key field must not be empty",
+    ));
+  }
+  let validator = compile_schema(&def.schema).map_err(|e| AppError::invalid_argument(e.message))?;
+  let schema = schemars::Schema::try_from(def.schema.clone())
+    .map_err(|e| AppError::invalid_argument(format!("Invalid schema: {}", e)))?;
+  Ok(match def.key_type {
+    KeyType::Integer => build::<u32>(def, schema, validator),
+    KeyType::String => build::<String>(def, schema, validator),
+    KeyType::Uuid => build::<UuidKey>(def, schema, validator),
+    KeyType::Composite { .. } => {
+      return Err(AppError::invalid_argument(
+        "Dynamic stores cannot have composite keys",
+      ))
+    }
+  })
+}
+
+/// Every persisted definition, sorted by id
+pub fn dynamic_store_defs() -> AppResult<Vec<DynamicStoreDef>> {
+  defs_tree()?
+    .iter()
+    .values()
+    .map(|bytes| Ok(serde_json::from_slice(&bytes?)?))
+    .collect()
+}
+
+/// Registers the stores defined at runtime in earlier sessions, after the compiled stores so that
+/// their ids take precedence.
+pub(super) async fn register_dynamic_stores() -> anyhow::Result<()> {
+  for def in dynamic_store_defs()? {
+    // A definition that no longer opens, or whose id a compiled store took since, must not keep
+    // the app from starting
+    let registered = match open(&def) {
+      Ok(appdata) => insert_appdata(appdata).await,
+      Err(e) => Err(e.into()),
+    };
+    if let Err(e) = registered {
+      log::error!("Skipping dynamic store: id={}, {}", def.id, e);
+    }
+  }
+  Ok(())
+}
+
+/// Registers a new store for `def` and persists the definition.
+pub async fn define_store(def: DynamicStoreDef) -> AppResult<StoreDescriptor> {
+  let appdata = open(&def)?;
+  if get(&def.id).await.is_some() {
+    return Err(AppError::new(
+      ErrorCode::Conflict,
+      format!("AppData already registered: {}", def.id),
+    ));
+  }
+  insert_appdata(appdata.clone())
+    .await
+    .map_err(|e| AppError::new(ErrorCode::Conflict, e.to_string()))?;
+  let persisted = serde_json::to_vec(&def)
+    .map_err(AppError::from)
+    .and_then(|bytes| Ok(defs_tree()?.insert(def.id.as_bytes(), bytes)?))
+    .and_then(|_| flush_db());
+  if let Err(e) = persisted {
+    remove_appdata(&def.id).await;
+    return Err(e);
+  }
+  appdata.descriptor()
+}
+
+/// Unregisters a dynamic store and deletes its definition, records, history and key sequence.
+///
+/// A definition that was skipped at startup, because it no longer opens or a compiled store took
+/// its id, is deleted on its own; the store registered under that id is left alone.
+pub async fn drop_store(id: &str) -> AppResult<()> {
+  let registered = get(id).await;
+  if !defs_tree()?.contains_key(id)? {
+    return Err(match registered {
+      Some(_) => AppError::invalid_argument(format!("Not a dynamic store: {}", id)),
+      None => AppError::not_found(format!("AppData not found: {}", id)),
+    });
+  }
+  if !registered.is_some_and(|appdata| appdata.dynamic()) {
+    let _guard = write_lock();
+    defs_tree()?.remove(id)?;
+    return flush_db();
+  }
+  {
+    // Writers check the definition under the same lock, see `tree`
+    let _guard = write_lock();
+    defs_tree()?.remove(id)?;
+    db().drop_tree(id)?;
+    history_tree(id)?.clear()?;
+    clear_revisions(id)?;
+    clear_sequence(id)?;
+    drop_trash(id)?;
+    flush_db()?;
+  }
+  remove_appdata(id).await;
+  notify(ChangeEvent {
+    store_id: id.to_owned(),
+    key: None,
+    operation: ChangeOperation::Remove,
+    revision: None,
+    value: None,
+  });
+  Ok(())
+}
+
+/// Lists the definitions of the stores created at runtime.
+#[tauri::command]
+pub async fn appdata_cmd_dynamic_stores() -> AppResult<Vec<DynamicStoreDef>> {
+  dynamic_store_defs()
+}
+
+/// Creates a store from a JSON Schema; it is registered again on every startup.
+#[tauri::command]
+pub async fn appdata_cmd_define_store(definition: DynamicStoreDef) -> AppResult<StoreDescriptor> {
+  define_store(definition).await
+}
+
+/// Deletes a store created with `appdata_cmd_define_store`, including all its records.
+#[tauri::command]
+pub async fn appdata_cmd_drop_store(schema_id: &str) -> AppResult<()> {
+  drop_store(schema_id).await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::storage::{init_test_storage, watch_changes, AppEntity};
+  use schemars::JsonSchema;
+  use serde_json::json;
+  use std::sync::Mutex;
+
+  #[derive(Debug, Default, Serialize, Deserialize, JsonSchema, crate::AppData)]
+  #[appdata(store = "DynamicTestCompiled", key = id)]
+  struct Compiled {
+    id: u32,
+  }
+
+  fn def(id: &str) -> DynamicStoreDef {
+    serde_json::from_value(json!({
+      "id": id,
+      "schema": {"type": "object", "properties": {"id": {"type": "integer"}, "text": {"type": "string"}}}
+    }))
+    .unwrap()
+  }
+
+  fn persist(def: &DynamicStoreDef) {
+    defs_tree()
+      .unwrap()
+      .insert(def.id.as_bytes(), serde_json::to_vec(def).unwrap())
+      .unwrap();
+  }
+
+  #[test]
+  fn test_open() {
+    let def: DynamicStoreDef = serde_json::from_value(json!({
+      "id": "Note",
+      "schema": {"type": "object", "properties": {"id": {"type": "integer"}}}
+    }))
+    .unwrap();
+    assert_eq!(
+      (def.key_field.as_str(), &def.key_type),
+      ("id", &KeyType::Integer)
+    );
+    let appdata = open(&def).unwrap();
+    assert_eq!(appdata.id(), "Note");
+    assert_eq!(
+      appdata.schema().get("x-key-type"),
+      Some(&json!({"kind": "integer"}))
+    );
+
+    let invalid = |def: DynamicStoreDef| open(&def).err().unwrap().code;
+    assert_eq!(
+      invalid(DynamicStoreDef {
+        id: "__meta".to_owned(),
+        ..def.clone()
+      }),
+      ErrorCode::InvalidArgument
+    );
+    for id in ["My Notes", "a/b", "Café"] {
+      assert_eq!(
+        invalid(DynamicStoreDef {
+          id: id.to_owned(),
+          ..def.clone()
+        }),
+        ErrorCode::InvalidArgument
+      );
+    }
+    assert_eq!(
+      invalid(DynamicStoreDef {
+        schema: json!({"type": 7}),
+        ..def.clone()
+      }),
+      ErrorCode::InvalidArgument
+    );
+    assert_eq!(
+      invalid(DynamicStoreDef {
+        key_type: KeyType::Composite { parts: vec![] },
+        ..def
+      }),
+      ErrorCode::InvalidArgument
+    );
+  }
+
+  #[tokio::test]
+  async fn test_register_skips_bad_definitions() {
+    init_test_storage().await;
+    let err = define_store(def("DynamicTestCompiled")).await.unwrap_err();
+    assert_eq!(err.code, ErrorCode::Conflict);
+
+    // Saved by an earlier version, before the ids clashed or the schema broke
+    persist(&def("DynamicTestCompiled"));
+    persist(&DynamicStoreDef {
+      schema: json!({"type": 7}),
+      ..def("DynamicTestBroken")
+    });
+    persist(&def("DynamicTestRestored"));
+    register_dynamic_stores().await.unwrap();
+    assert!(get("DynamicTestCompiled")
+      .await
+      .unwrap()
+      .type_name()
+      .ends_with("Compiled"));
+    assert!(get("DynamicTestBroken").await.is_none());
+    assert!(get("DynamicTestRestored").await.is_some());
+
+    // Dropping the skipped definitions deletes them and nothing else
+    AppEntity::save_and_flush(&Compiled { id: 1 }).unwrap();
+    for id in ["DynamicTestCompiled", "DynamicTestBroken"] {
+      drop_store(id).await.unwrap();
+      assert!(!defs_tree().unwrap().contains_key(id).unwrap());
+    }
+    assert!(get("DynamicTestCompiled").await.is_some());
+    assert!(<Compiled as AppEntity>::exists_data(&1).unwrap());
+    let err = drop_store("DynamicTestCompiled").await.unwrap_err();
+    assert_eq!(err.code, ErrorCode::InvalidArgument);
+  }
+
+  static DROPPED: Mutex<Vec<ChangeEvent>> = Mutex::new(vec![]);
+
+  #[tokio::test]
+  async fn test_drop_store() {
+    init_test_storage().await;
+    watch_changes(|event| {
+      if event.store_id == "DynamicTestDropped" && event.key.is_none() {
+        DROPPED.lock().unwrap().push(event.clone());
+      }
+    });
+    define_store(def("DynamicTestDropped")).await.unwrap();
+    let appdata = get("DynamicTestDropped").await.unwrap();
+    let record = appdata.create_data(br#"{"text": "a"}"#).unwrap();
+    assert_eq!(record.key, json!(1));
+
+    drop_store("DynamicTestDropped").await.unwrap();
+    assert!(get("DynamicTestDropped").await.is_none());
+    assert_eq!(
+      DROPPED.lock().unwrap().as_slice(),
+      [ChangeEvent {
+        store_id: "DynamicTestDropped".to_owned(),
+        key: None,
+        operation: ChangeOperation::Remove,
+        revision: None,
+        value: None,
+      }]
+    );
+    // A writer still holding the store cannot bring the tree back
+    let err = appdata
+      .save_and_flush(br#"{"id": 2, "text": "b"}"#)
+      .unwrap_err();
+    assert_eq!(err.code, ErrorCode::NotFound);
+    assert!(!db()
+      .tree_names()
+      .iter()
+      .any(|name| name.as_ref() == b"DynamicTestDropped"));
+
+    // The key sequence starts over
+    define_store(def("DynamicTestDropped")).await.unwrap();
+    let appdata = get("DynamicTestDropped").await.unwrap();
+    let record = appdata.create_data(br#"{"text": "c"}"#).unwrap();
+    assert_eq!(record.key, json!(1));
+  }
+}
@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::FieldError;
+
+/// Stable error codes the frontend can match on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+  /// The store or record does not exist
+  NotFound,
+  /// The record violates its schema
+  ValidationFailed,
+  /// The payload or stored bytes could not be decoded
+  DecodeError,
+  /// The underlying database failed
+  StorageIo,
+  /// The request itself is malformed
+  InvalidArgument,
+  /// The record changed since the expected revision was read
+  Conflict,
+  /// Anything else, including initialization order problems
+  Internal,
+}
+
+/// Error returned by the appdata subsystem, every `appdata_cmd_*` command and the config module
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppError {
+  pub code: ErrorCode,
+  pub message: String,
+  /// JSON pointer of the offending value, if the error concerns one field
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub path: Option<String>,
+  /// Every schema violation, for `ValidationFailed`
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub fields: Vec<FieldError>,
+  /// Messages of the underlying causes, outermost first
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub sources: Vec<String>,
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+impl AppError {
+  pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+    Self {
+      code,
+      message: message.into(),
+      path: None,
+      fields: Vec::new(),
+      sources: Vec::new(),
+    }
+  }
+
+  /// Builds an error from `source`, keeping its `source()` chain.
+  pub fn from_source(code: ErrorCode, source: &(dyn std::error::Error + 'static)) -> Self {
+    let mut sources = Vec::new();
+    let mut cause = source.source();
+    while let Some(e) = cause {
+      sources.push(e.to_string());
+      cause = e.source();
+    }
+    Self {
+      sources,
+      ..Self::new(code, source.to_string())
+    }
+  }
+
+  pub fn not_found(message: impl Into<String>) -> Self {
+    Self::new(ErrorCode::NotFound, message)
+  }
+
+  pub fn invalid_argument(message: impl Into<String>) -> Self {
+    Self::new(ErrorCode::InvalidArgument, message)
+  }
+
+  pub fn internal(message: impl Into<String>) -> Self {
+    Self::new(ErrorCode::Internal, message)
+  }
+
+  pub fn validation(fields: Vec<FieldError>) -> Self {
+    let details = fields
+      .iter()
+      .map(|e| {
+        format!(
+          "{}: {}",
+          if e.path.is_empty() { "/" } else { &e.path },
+          e.message
+        )
+      })
+      .collect::<Vec<_>>()
+      .join("; ");
+    Self {
+      path: fields.first().map(|e| e.path.clone()),
+      fields,
+      ..Self::new(
+        ErrorCode::ValidationFailed,
+        format!("Validation failed: {}", details),
+      )
+    }
+  }
+
+  pub fn with_path(mut self, path: impl Into<String>) -> Self {
+    self.path = Some(path.into());
+    self
+  }
+}
+
+impl fmt::Display for AppError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "[{:?}] {}", self.code, self.message)?;
+    if let Some(path) = &self.path {
+      write!(f, " (at {})", path)?;
+    }
+    for source in &self.sources {
+      write!(f, ": {}", source)?;
+    }
+    Ok(())
+  }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<serde_json::Error> for AppError {
+  fn from(e: serde_json::Error) -> Self {
+    Self::from_source(ErrorCode::DecodeError, &e)
+  }
+}
+
+impl From<::sled::Error> for AppError {
+  fn from(e: ::sled::Error) -> Self {
+    Self::from_source(ErrorCode::StorageIo, &e)
+  }
+}
+
+impl From<reindeer::Error> for AppError {
+  fn from(e: reindeer::Error) -> Self {
+    Self::from_source(ErrorCode::StorageIo, &e)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn test_error_serialization() {
+    let err = AppError::validation(vec![FieldError {
+      path: "/age".to_string(),
+      message: "200 is greater than the maximum of 150".to_string(),
+    }]);
+    assert_eq!(
+      serde_json::to_value(&err).unwrap(),
+      json!({
+        "code": "VALIDATION_FAILED",
+        "message": "Validation failed: /age: 200 is greater than the maximum of 150",
+        "path": "/age",
+        "fields": [{"path": "/age", "message": "200 is greater than the maximum of 150"}],
+      })
+    );
+    let err: AppError = serde_json::from_slice::<u32>(b"\"x\"").unwrap_err().into();
+    assert_eq!(err.code, ErrorCode::DecodeError);
+    assert_eq!(
+      serde_json::to_value(AppError::not_found("AppData not found: x")).unwrap(),
+      json!({"code": "NOT_FOUND", "message": "AppData not found: x"})
+    );
+  }
+}
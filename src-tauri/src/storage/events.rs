@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::{Mutex, OnceLock};
+use tauri::Emitter;
+
+use super::{AppError, AppResult};
+
+/// Base name of the change events; the frontend listens to
+/// `appdata://change/<store>` or `appdata://change/<store>/<key>`.
+pub const CHANGE_EVENT: &str = "appdata://change";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeOperation {
+  Save,
+  Remove,
+  Import,
+}
+
+/// Payload emitted after every successful mutation of a store
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeEvent {
+  pub store_id: String,
+  /// `None` when the whole store changed, e.g. after an import
+  pub key: Option<Value>,
+  pub operation: ChangeOperation,
+  /// Revision after the change; `0` once removed, `None` after an import
+  pub revision: Option<u64>,
+  /// The record as saved; `None` for removals and imports
+  pub value: Option<Value>,
+}
+
+impl ChangeEvent {
+  /// Names this event is emitted under, from the broadest to the most specific.
+  pub fn event_names(&self) -> Vec<String> {
+    let store = format!("{}/{}", CHANGE_EVENT, self.store_id);
+    match &self.key {
+      Some(key) => vec![store.clone(), format!("{}/{}", store, key_segment(key))],
+      None => vec![store],
+    }
+  }
+}
+
+/// Renders a key as an event name segment. Tauri only allows alphanumerics and `-/:_` in event
+/// names, so other characters become `_` and listeners should still compare `key`.
+pub fn key_segment(key: &Value) -> String {
+  let raw = match key {
+    Value::String(s) => s.clone(),
+    Value::Array(parts) => parts.iter().map(key_segment).collect::<Vec<_>>().join(":"),
+    other => other.to_string(),
+  };
+  raw
+    .chars()
+    .map(|c| {
+      if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':') {
+        c
+      } else {
+        '_'
+      }
+    })
+    .collect()
+}
+
+type EmitFn = Box<dyn Fn(&str, &ChangeEvent) + Send + Sync>;
+
+static EMITTER: OnceLock<EmitFn> = OnceLock::new();
+
+pub fn init_events<R: tauri::Runtime>(app: tauri::AppHandle<R>) -> AppResult<()> {
+  let emit: EmitFn = Box::new(move |name, event| {
+    if let Err(e) = app.emit(name, event) {
+      log::warn!("Failed to emit {}: {}", name, e);
+    }
+  });
+  EMITTER
+    .set(emit)
+    .map_err(|_| AppError::internal("AppData events already initialized"))
+}
+
+static WATCHERS: Mutex<Vec<for<'a> fn(&'a ChangeEvent)>> = Mutex::new(vec![]);
+
+/// Registers an in-process listener, called before the event reaches the webview.
+pub fn watch_changes(f: for<'a> fn(&'a ChangeEvent)) {
+  WATCHERS.lock().unwrap().push(f);
+}
+
+pub(super) fn notify(event: ChangeEvent) {
+  let watchers = WATCHERS.lock().unwrap().clone();
+  for f in watchers {
+    f(&event);
+  }
+  if let Some(emit) = EMITTER.get() {
+    for name in event.event_names() {
+      emit(&name, &event);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn event(key: Option<Value>) -> ChangeEvent {
+    ChangeEvent {
+      store_id: "Member".to_owned(),
+      key,
+      operation: ChangeOperation::Save,
+      revision: Some(1),
+      value: None,
+    }
+  }
+
+  #[test]
+  fn test_key_segment() {
+    assert_eq!(key_segment(&json!(42)), "42");
+    assert_eq!(key_segment(&json!("a-b_c")), "a-b_c");
+    assert_eq!(key_segment(&json!("a b/c.d")), "a_b_c_d");
+    assert_eq!(key_segment(&json!(["acme", 7])), "acme:7");
+    assert_eq!(key_segment(&json!(["a:b", ["c d", 1]])), "a:b:c_d:1");
+  }
+
+  #[test]
+  fn test_event_names() {
+    assert_eq!(event(None).event_names(), vec!["appdata://change/Member"]);
+    assert_eq!(
+      event(Some(json!(3))).event_names(),
+      vec!["appdata://change/Member", "appdata://change/Member/3"]
+    );
+    assert_eq!(
+      event(Some(json!(["acme", 7]))).event_names(),
+      vec!["appdata://change/Member", "appdata://change/Member/acme:7"]
+    );
+  }
+}
@@ -0,0 +1,666 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::sync::{Arc, LazyLock, RwLock};
+
+use super::{
+  apply_batch, get_ok, get_writable, AppData, AppError, AppResult, ArchivedStore, BatchOp,
+  ErrorCode,
+};
+
+/// Records read from a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataFile {
+  /// Schema version the records were written in; `None` if the file does not say, e.g. when it
+  /// was written by hand, in which case they are taken to be current
+  pub version: Option<u32>,
+  pub records: Vec<Value>,
+}
+
+/// A file format records can be exported to and imported from.
+///
+/// Formats work on the JSON form of records; `schema` describes the store, for formats that need
+/// to know its fields up front. The schema version is written along with the records, so that
+/// imports can upgrade them like archives.
+pub trait DataFormat: Send + Sync {
+  /// Name the format is selected by, e.g. `csv`
+  fn name(&self) -> &'static str;
+  fn write(
+    &self,
+    schema: &schemars::Schema,
+    version: u32,
+    records: &[Value],
+    out: &mut dyn Write,
+  ) -> AppResult<()>;
+  fn read(&self, schema: &schemars::Schema, input: &mut dyn Read) -> AppResult<DataFile>;
+}
+
+static FORMATS: LazyLock<RwLock<HashMap<&'static str, Arc<dyn DataFormat>>>> =
+  LazyLock::new(|| {
+    let formats: [Arc<dyn DataFormat>; 5] = [
+      Arc::new(JsonFormat),
+      Arc::new(NdjsonFormat),
+      Arc::new(CsvFormat),
+      Arc::new(TomlFormat),
+      Arc::new(YamlFormat),
+    ];
+    RwLock::new(formats.into_iter().map(|f| (f.name(), f)).collect())
+  });
+
+/// Makes a format available to the export and import commands.
+pub fn register_format(format: impl DataFormat + 'static) -> anyhow::Result<()> {
+  let mut formats = FORMATS.write().unwrap_or_else(|e| e.into_inner());
+  if formats.contains_key(format.name()) {
+    return Err(anyhow::anyhow!(
+      "Data format already registered: name={}",
+      format.name()
+    ));
+  }
+  formats.insert(format.name(), Arc::new(format));
+  Ok(())
+}
+
+pub fn data_format(name: &str) -> AppResult<Arc<dyn DataFormat>> {
+  FORMATS
+    .read()
+    .unwrap_or_else(|e| e.into_inner())
+    .get(name)
+    .cloned()
+    .ok_or_else(|| AppError::invalid_argument(format!("Unknown data format: {}", name)))
+}
+
+pub(super) fn open_file(path: &str) -> AppResult<File> {
+  File::open(path).map_err(|e| {
+    AppError::new(
+      ErrorCode::StorageIo,
+      format!("Failed to open file: path={}, {}", path, e),
+    )
+  })
+}
+
+pub(super) fn create_file(path: &str) -> AppResult<File> {
+  File::create(path).map_err(|e| {
+    AppError::new(
+      ErrorCode::StorageIo,
+      format!("Failed to create file: path={}, {}", path, e),
+    )
+  })
+}
+
+pub(super) fn io_error(e: std::io::Error) -> AppError {
+  AppError::new(ErrorCode::StorageIo, e.to_string())
+}
+
+fn decode_error(format: &str, e: impl std::fmt::Display) -> AppError {
+  AppError::new(
+    ErrorCode::DecodeError,
+    format!("Invalid {} input: {}", format, e),
+  )
+}
+
+fn encode_error(format: &str, e: impl std::fmt::Display) -> AppError {
+  AppError::invalid_argument(format!("Records cannot be written as {}: {}", format, e))
+}
+
+/// Reads the records of a file written in `format` for `appdata`, with the schema version to
+/// upgrade them from.
+pub(super) fn read_records(
+  format: &str,
+  appdata: &dyn AppData,
+  path: &str,
+) -> AppResult<ArchivedStore> {
+  let mut input = BufReader::new(open_file(path)?);
+  let file = data_format(format)?.read(&appdata.schema(), &mut input)?;
+  Ok(ArchivedStore {
+    version: file.version.unwrap_or(appdata.version()),
+    records: file.records,
+  })
+}
+
+/// A JSON document with the version and the records, such as `ArchivedStore`; a bare array of
+/// records, as written by reindeer's `export_json`, is read too.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonDocument {
+  Versioned(ArchivedStore),
+  Records(Vec<Value>),
+}
+
+impl From<JsonDocument> for DataFile {
+  fn from(document: JsonDocument) -> Self {
+    match document {
+      JsonDocument::Versioned(store) => DataFile {
+        version: Some(store.version),
+        records: store.records,
+      },
+      JsonDocument::Records(records) => DataFile {
+        version: None,
+        records,
+      },
+    }
+  }
+}
+
+/// A JSON object holding the version and the array of records
+pub struct JsonFormat;
+
+impl DataFormat for JsonFormat {
+  fn name(&self) -> &'static str {
+    "json"
+  }
+  fn write(
+    &self,
+    _: &schemars::Schema,
+    version: u32,
+    records: &[Value],
+    out: &mut dyn Write,
+  ) -> AppResult<()> {
+    let store = ArchivedStore {
+      version,
+      records: records.to_vec(),
+    };
+    Ok(serde_json::to_writer_pretty(out, &store)?)
+  }
+  fn read(&self, _: &schemars::Schema, input: &mut dyn Read) -> AppResult<DataFile> {
+    let document: JsonDocument = serde_json::from_reader(input)?;
+    Ok(document.into())
+  }
+}
+
+/// A `{"version": ...}` line, then one JSON record per line
+pub struct NdjsonFormat;
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NdjsonHeader {
+  version: u32,
+}
+
+impl DataFormat for NdjsonFormat {
+  fn name(&self) -> &'static str {
+    "ndjson"
+  }
+  fn write(
+    &self,
+    _: &schemars::Schema,
+    version: u32,
+    records: &[Value],
+    out: &mut dyn Write,
+  ) -> AppResult<()> {
+    serde_json::to_writer(&mut *out, &NdjsonHeader { version })?;
+    out.write_all(b"\n").map_err(io_error)?;
+    for record in records {
+      serde_json::to_writer(&mut *out, record)?;
+      out.write_all(b"\n").map_err(io_error)?;
+    }
+    Ok(())
+  }
+  fn read(&self, _: &schemars::Schema, input: &mut dyn Read) -> AppResult<DataFile> {
+    let mut records = serde_json::Deserializer::from_reader(input)
+      .into_iter()
+      .map(|record| Ok(record?))
+      .collect::<AppResult<Vec<Value>>>()?;
+    // Files without the header line hold records only
+    let version = match records.first().map(NdjsonHeader::deserialize) {
+      Some(Ok(header)) => Some(header.version),
+      _ => None,
+    };
+    if version.is_some() {
+      records.remove(0);
+    }
+    Ok(DataFile { version, records })
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TomlDocument {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  version: Option<u32>,
+  records: Vec<Value>,
+}
+
+/// A `version` key and an array of `[[records]]` tables; cannot hold `null` values
+pub struct TomlFormat;
+
+impl DataFormat for TomlFormat {
+  fn name(&self) -> &'static str {
+    "toml"
+  }
+  fn write(
+    &self,
+    _: &schemars::Schema,
+    version: u32,
+    records: &[Value],
+    out: &mut dyn Write,
+  ) -> AppResult<()> {
+    let text = toml::to_string_pretty(&TomlDocument {
+      version: Some(version),
+      records: records.to_vec(),
+    })
+    .map_err(|e| encode_error(self.name(), e))?;
+    out.write_all(text.as_bytes()).map_err(io_error)
+  }
+  fn read(&self, _: &schemars::Schema, input: &mut dyn Read) -> AppResult<DataFile> {
+    let mut text = String::new();
+    input.read_to_string(&mut text).map_err(io_error)?;
+    let document: TomlDocument = toml::from_str(&text).map_err(|e| decode_error(self.name(), e))?;
+    Ok(DataFile {
+      version: document.version,
+      records: document.records,
+    })
+  }
+}
+
+/// A YAML mapping holding the version and the sequence of records; a bare sequence of records is
+/// read too.
+pub struct YamlFormat;
+
+impl DataFormat for YamlFormat {
+  fn name(&self) -> &'static str {
+    "yaml"
+  }
+  fn write(
+    &self,
+    _: &schemars::Schema,
+    version: u32,
+    records: &[Value],
+    out: &mut dyn Write,
+  ) -> AppResult<()> {
+    let store = ArchivedStore {
+      version,
+      records: records.to_vec(),
+    };
+    serde_yaml::to_writer(out, &store).map_err(|e| encode_error(self.name(), e))
+  }
+  fn read(&self, _: &schemars::Schema, input: &mut dyn Read) -> AppResult<DataFile> {
+    let document: JsonDocument =
+      serde_yaml::from_reader(input).map_err(|e| decode_error(self.name(), e))?;
+    Ok(document.into())
+  }
+}
+
+/// One row per record and one column per leaf field of the schema, nested objects flattened into
+/// dotted column names such as `features.dark_mode`.
+///
+/// Arrays and maps are written as JSON text. Cells are read back using the type of their column,
+/// so records round-trip as long as their fields match the schema.
+pub struct CsvFormat;
+
+/// Follows `$ref`s to the schema they point to within `root`.
+pub(super) fn resolve<'a>(root: &'a Value, mut schema: &'a Value) -> &'a Value {
+  for _ in 0..32 {
+    match schema
+      .get("$ref")
+      .and_then(Value::as_str)
+      .and_then(|r| r.strip_prefix('#'))
+      .and_then(|pointer| root.pointer(pointer))
+    {
+      Some(target) => schema = target,
+      None => break,
+    }
+  }
+  schema
+}
+
+/// A leaf field of a schema, as flattened into a CSV column
+pub(super) struct LeafColumn<'a> {
+  pub path: Vec<String>,
+  /// Schema of the field, with `$ref`s resolved
+  pub schema: &'a Value,
+  pub title: Option<&'a str>,
+}
+
+impl LeafColumn<'_> {
+  /// Column name, with the path segments joined by dots
+  pub fn name(&self) -> String {
+    self.path.join(".")
+  }
+}
+
+/// Collects the leaf fields of the store schema `root`.
+pub(super) fn leaf_columns(root: &Value) -> Vec<LeafColumn<'_>> {
+  let mut out = Vec::new();
+  collect_leaves(root, root, vec![], &mut out);
+  out
+}
+
+fn collect_leaves<'a>(
+  root: &'a Value,
+  schema: &'a Value,
+  prefix: Vec<String>,
+  out: &mut Vec<LeafColumn<'a>>,
+) {
+  let resolved = resolve(root, schema);
+  match resolved.get("properties").and_then(Value::as_object) {
+    // Recursive types are cut off and written as JSON
+    Some(properties) if prefix.len() < 8 => {
+      for (name, property) in properties {
+        let mut path = prefix.clone();
+        path.push(name.clone());
+        collect_leaves(root, property, path, out);
+      }
+    }
+    _ => out.push(LeafColumn {
+      path: prefix,
+      schema: resolved,
+      title: schema
+        .get("title")
+        .or_else(|| resolved.get("title"))
+        .and_then(Value::as_str),
+    }),
+  }
+}
+
+fn schema_types(schema: &Value) -> Vec<&str> {
+  match schema.get("type") {
+    Some(Value::String(t)) => vec![t.as_str()],
+    Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+    _ => vec![],
+  }
+}
+
+/// Values allowed by `enum` and `const`, including those of `oneOf`/`anyOf` alternatives.
+fn enum_values<'a>(root: &'a Value, schema: &'a Value, out: &mut Vec<&'a Value>) {
+  let schema = resolve(root, schema);
+  if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+    out.extend(values);
+  }
+  if let Some(value) = schema.get("const") {
+    out.push(value);
+  }
+  for keyword in ["oneOf", "anyOf"] {
+    for alternative in schema
+      .get(keyword)
+      .and_then(Value::as_array)
+      .into_iter()
+      .flatten()
+    {
+      enum_values(root, alternative, out);
+    }
+  }
+}
+
+fn format_cell(value: Option<&Value>) -> String {
+  match value {
+    None | Some(Value::Null) => String::new(),
+    Some(Value::String(s)) => s.clone(),
+    Some(value) => value.to_string(),
+  }
+}
+
+fn parse_bool(cell: &str) -> Option<bool> {
+  match cell.to_ascii_lowercase().as_str() {
+    "true" | "yes" | "y" | "1" => Some(true),
+    "false" | "no" | "n" | "0" => Some(false),
+    _ => None,
+  }
+}
+
+/// Reads a cell as the type of its field, given by its schema within `root`; `Ok(None)` leaves
+/// the field out of the record.
+///
+/// Enum values match case-insensitively. Fields of unknown type are read as JSON, or as strings
+/// if they are not JSON.
+pub(super) fn coerce_cell(
+  root: &Value,
+  schema: Option<&Value>,
+  cell: &str,
+) -> Result<Option<Value>, String> {
+  let types = schema.map(schema_types).unwrap_or_default();
+  if cell.is_empty() {
+    return Ok(if types.contains(&"null") {
+      Some(Value::Null)
+    } else if types.contains(&"string") {
+      Some(Value::String(String::new()))
+    } else {
+      None
+    });
+  }
+  let mut choices = Vec::new();
+  if let Some(schema) = schema {
+    enum_values(root, schema, &mut choices);
+  }
+  if !choices.is_empty() {
+    return choices
+      .iter()
+      .find(|choice| match choice {
+        Value::String(s) => s.eq_ignore_ascii_case(cell),
+        choice => choice.to_string() == cell,
+      })
+      .map(|choice| Some((*choice).clone()))
+      .ok_or_else(|| {
+        format!(
+          "{:?} is not one of {}",
+          cell,
+          choices
+            .iter()
+            .map(|choice| choice.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+        )
+      });
+  }
+  for t in &types {
+    let parsed = match *t {
+      "integer" => cell
+        .parse::<i64>()
+        .map(Value::from)
+        .or_else(|_| cell.parse::<u64>().map(Value::from))
+        .ok(),
+      "number" => cell
+        .parse::<f64>()
+        .ok()
+        .and_then(|n| serde_json::Number::from_f64(n).map(Value::Number)),
+      "boolean" => parse_bool(cell).map(Value::Bool),
+      "array" | "object" => serde_json::from_str::<Value>(cell)
+        .ok()
+        .filter(|value| value.is_array() || value.is_object()),
+      _ => None,
+    };
+    if parsed.is_some() {
+      return Ok(parsed);
+    }
+  }
+  if types.is_empty() {
+    return Ok(Some(
+      serde_json::from_str(cell).unwrap_or_else(|_| Value::String(cell.to_owned())),
+    ));
+  }
+  if types.contains(&"string") {
+    return Ok(Some(Value::String(cell.to_owned())));
+  }
+  Err(format!("{:?} is not of type {}", cell, types.join(" or ")))
+}
+
+pub(super) fn set_path(record: &mut Value, path: &[String], value: Value) {
+  let mut target = record;
+  for segment in &path[..path.len() - 1] {
+    if !target[segment.as_str()].is_object() {
+      target[segment.as_str()] = Value::Object(Default::default());
+    }
+    target = &mut target[segment.as_str()];
+  }
+  target[path[path.len() - 1].as_str()] = value;
+}
+
+pub(super) fn csv_error(e: csv::Error) -> AppError {
+  decode_error("csv", e)
+}
+
+/// Starts the line `CsvFormat` writes the version on, before the header
+const CSV_VERSION_PREFIX: &str = "# version: ";
+
+/// Splits the version line off CSV text, if it has one.
+pub(super) fn split_csv_version(text: &str) -> AppResult<(Option<u32>, &str)> {
+  let Some(rest) = text.strip_prefix(CSV_VERSION_PREFIX) else {
+    return Ok((None, text));
+  };
+  let (line, rest) = rest.split_once('\n').unwrap_or((rest, ""));
+  let version = line.trim().parse().map_err(|e| decode_error("csv", e))?;
+  Ok((Some(version), rest))
+}
+
+impl DataFormat for CsvFormat {
+  fn name(&self) -> &'static str {
+    "csv"
+  }
+  fn write(
+    &self,
+    schema: &schemars::Schema,
+    version: u32,
+    records: &[Value],
+    out: &mut dyn Write,
+  ) -> AppResult<()> {
+    writeln!(out, "{}{}", CSV_VERSION_PREFIX, version).map_err(io_error)?;
+    let columns = leaf_columns(schema.as_value());
+    let mut writer = csv::Writer::from_writer(out);
+    writer
+      .write_record(columns.iter().map(LeafColumn::name))
+      .map_err(csv_error)?;
+    for record in records {
+      writer
+        .write_record(columns.iter().map(|column| {
+          format_cell(
+            column
+              .path
+              .iter()
+              .try_fold(record, |value, segment| value.get(segment)),
+          )
+        }))
+        .map_err(csv_error)?;
+    }
+    writer.flush().map_err(io_error)
+  }
+  fn read(&self, schema: &schemars::Schema, input: &mut dyn Read) -> AppResult<DataFile> {
+    let mut text = String::new();
+    input.read_to_string(&mut text).map_err(io_error)?;
+    let (version, text) = split_csv_version(&text)?;
+    let root = schema.as_value();
+    let columns: HashMap<String, &Value> = leaf_columns(root)
+      .into_iter()
+      .map(|column| (column.name(), column.schema))
+      .collect();
+    let mut reader = csv::Reader::from_reader(text.as_bytes());
+    let headers = reader.headers().map_err(csv_error)?.clone();
+    let mut records = Vec::new();
+    for row in reader.records() {
+      let row = row.map_err(csv_error)?;
+      let mut record = Value::Object(Default::default());
+      for (header, cell) in headers.iter().zip(row.iter()) {
+        // Mismatched cells are kept as strings, so validation reports them
+        let value = coerce_cell(root, columns.get(header).copied(), cell)
+          .unwrap_or_else(|_| Some(Value::String(cell.to_owned())));
+        if let Some(value) = value {
+          let path: Vec<String> = header.split('.').map(str::to_owned).collect();
+          set_path(&mut record, &path, value);
+        }
+      }
+      records.push(record);
+    }
+    Ok(DataFile { version, records })
+  }
+}
+
+/// Names of the formats accepted by `appdata_cmd_export_data` and `appdata_cmd_import_data`.
+#[tauri::command]
+pub async fn appdata_cmd_data_formats() -> AppResult<Vec<String>> {
+  let mut names: Vec<String> = FORMATS
+    .read()
+    .unwrap_or_else(|e| e.into_inner())
+    .keys()
+    .map(|name| name.to_string())
+    .collect();
+  names.sort();
+  Ok(names)
+}
+
+/// Writes every record of a store to a file in the given format, returning how many were written.
+#[tauri::command]
+pub async fn appdata_cmd_export_data(
+  schema_id: &str,
+  path: String,
+  format: String,
+) -> AppResult<usize> {
+  let appdata = get_ok(schema_id).await?;
+  let format = data_format(&format)?;
+  let records = appdata.all_values()?;
+  let mut out = BufWriter::new(create_file(&path)?);
+  format.write(&appdata.schema(), appdata.version(), &records, &mut out)?;
+  out.flush().map_err(io_error)?;
+  Ok(records.len())
+}
+
+/// Saves the records of a file in the given format to a store, in one transaction.
+///
+/// Records are upgraded from the version they were exported in. Records with an existing key
+/// replace it; nothing is written if any record is invalid.
+#[tauri::command]
+pub async fn appdata_cmd_import_data(
+  schema_id: &str,
+  path: String,
+  format: String,
+) -> AppResult<usize> {
+  let appdata = get_writable(schema_id).await?;
+  let file = read_records(&format, appdata.as_ref(), &path)?;
+  let ops = file
+    .records
+    .into_iter()
+    .enumerate()
+    .map(|(index, record)| {
+      let record = appdata.upgrade(file.version, record).map_err(|mut e| {
+        e.message = format!("Record {} cannot be upgraded: {}", index, e.message);
+        e
+      })?;
+      Ok(BatchOp::Save {
+        schema_id: schema_id.to_owned(),
+        data: serde_json::to_vec(&record)?,
+        expected_revision: None,
+      })
+    })
+    .collect::<AppResult<Vec<_>>>()?;
+  let count = ops.len();
+  apply_batch(ops).await?;
+  Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::AppConfig;
+
+  fn round_trip(format: &str, records: &[Value]) -> DataFile {
+    let schema = schemars::schema_for!(AppConfig);
+    let format = data_format(format).unwrap();
+    let mut out = Vec::new();
+    format.write(&schema, 3, records, &mut out).unwrap();
+    format.read(&schema, &mut out.as_slice()).unwrap()
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let records = vec![serde_json::to_value(AppConfig::default()).unwrap(); 2];
+    for format in ["json", "ndjson", "csv", "toml", "yaml"] {
+      let file = round_trip(format, &records);
+      assert_eq!(file.version, Some(3), "format={}", format);
+      assert_eq!(file.records, records, "format={}", format);
+    }
+  }
+
+  #[test]
+  fn test_read_without_version() {
+    let schema = schemars::schema_for!(AppConfig);
+    let record = serde_json::to_value(AppConfig::default()).unwrap();
+    let json = serde_json::to_vec(&vec![record.clone()]).unwrap();
+    let ndjson = serde_json::to_vec(&record).unwrap();
+    for (format, input) in [("json", json), ("ndjson", ndjson)] {
+      let file = data_format(format)
+        .unwrap()
+        .read(&schema, &mut input.as_slice())
+        .unwrap();
+      assert_eq!(file.version, None, "format={}", format);
+      assert_eq!(file.records, vec![record.clone()], "format={}", format);
+    }
+  }
+}
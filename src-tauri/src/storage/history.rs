@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{db, get_ok, get_writable, AppError, AppResult, ChangeOperation};
+
+/// Revisions kept per record; older ones are pruned on write
+pub const HISTORY_LIMIT: usize = 20;
+
+/// The state of a record before a save or remove
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Revision {
+  pub id: u64,
+  /// Milliseconds since the Unix epoch
+  pub timestamp: i64,
+  /// The operation that replaced this state
+  pub operation: ChangeOperation,
+  /// The previous record; `None` if it did not exist yet
+  pub value: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevisionSummary {
+  pub id: u64,
+  pub timestamp: i64,
+  pub operation: ChangeOperation,
+  pub existed: bool,
+}
+
+pub(super) fn history_tree(store: &str) -> AppResult<::sled::Tree> {
+  Ok(db().open_tree(format!("__history/{}", store))?)
+}
+
+/// Length-prefixed so that one key is never a prefix of another.
+fn record_prefix(key_bytes: &[u8]) -> Vec<u8> {
+  let mut prefix = (key_bytes.len() as u32).to_be_bytes().to_vec();
+  prefix.extend_from_slice(key_bytes);
+  prefix
+}
+
+/// Key and bytes of a new history entry holding the previous state of a record.
+pub(super) fn history_entry(
+  key_bytes: &[u8],
+  operation: ChangeOperation,
+  previous: Option<Value>,
+) -> AppResult<(Vec<u8>, Vec<u8>)> {
+  let revision = Revision {
+    id: db().generate_id()?,
+    timestamp: chrono::Utc::now().timestamp_millis(),
+    operation,
+    value: previous,
+  };
+  let mut history_key = record_prefix(key_bytes);
+  history_key.extend_from_slice(&revision.id.to_be_bytes());
+  Ok((history_key, serde_json::to_vec(&revision)?))
+}
+
+/// History keys of a record, oldest first.
+pub(super) fn history_keys(tree: &::sled::Tree, key_bytes: &[u8]) -> AppResult<Vec<Vec<u8>>> {
+  tree
+    .scan_prefix(record_prefix(key_bytes))
+    .keys()
+    .map(|key| Ok(key?.to_vec()))
+    .collect()
+}
+
+/// Revisions of a record, newest first.
+pub fn list_revisions(store: &str, key_bytes: &[u8]) -> AppResult<Vec<Revision>> {
+  let mut revisions = history_tree(store)?
+    .scan_prefix(record_prefix(key_bytes))
+    .values()
+    .map(|value| Ok(serde_json::from_slice(&value?)?))
+    .collect::<AppResult<Vec<Revision>>>()?;
+  revisions.reverse();
+  Ok(revisions)
+}
+
+pub fn get_revision(store: &str, key_bytes: &[u8], id: u64) -> AppResult<Option<Revision>> {
+  let mut history_key = record_prefix(key_bytes);
+  history_key.extend_from_slice(&id.to_be_bytes());
+  match history_tree(store)?.get(history_key)? {
+    Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+    None => Ok(None),
+  }
+}
+
+#[tauri::command]
+pub async fn appdata_cmd_list_revisions(
+  schema_id: &str,
+  key: Value,
+) -> AppResult<Vec<RevisionSummary>> {
+  let appdata = get_ok(schema_id).await?;
+  Ok(
+    list_revisions(appdata.id(), &appdata.encode_key(&key)?)?
+      .into_iter()
+      .map(|r| RevisionSummary {
+        id: r.id,
+        timestamp: r.timestamp,
+        operation: r.operation,
+        existed: r.value.is_some(),
+      })
+      .collect(),
+  )
+}
+
+#[tauri::command]
+pub async fn appdata_cmd_get_revision(
+  schema_id: &str,
+  key: Value,
+  revision_id: u64,
+) -> AppResult<Option<Revision>> {
+  let appdata = get_ok(schema_id).await?;
+  get_revision(appdata.id(), &appdata.encode_key(&key)?, revision_id)
+}
+
+/// Makes a past revision the current value; restoring a revision taken before the record existed
+/// removes it. The restore itself is recorded as a new revision.
+#[tauri::command]
+pub async fn appdata_cmd_restore_revision(
+  schema_id: &str,
+  key: Value,
+  revision_id: u64,
+) -> AppResult<()> {
+  let appdata = get_writable(schema_id).await?;
+  let revision =
+    get_revision(appdata.id(), &appdata.encode_key(&key)?, revision_id)?.ok_or_else(|| {
+      AppError::not_found(format!(
+        "Revision not found: id={}, key={}, revision={}",
+        schema_id, key, revision_id
+      ))
+    })?;
+  match revision.value {
+    Some(value) => appdata.save_and_flush(&serde_json::to_vec(&value)?),
+    None => appdata.remove_and_flush(&key),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::storage::{init_test_storage, AppEntity};
+  use reindeer::AsBytes;
+  use schemars::JsonSchema;
+  use serde_json::json;
+
+  #[derive(
+    Debug, Default, Clone, PartialEq, Serialize, Deserialize, JsonSchema, crate::AppData,
+  )]
+  #[appdata(store = "HistoryTestItem", key = id)]
+  struct Item {
+    id: u32,
+    n: u32,
+  }
+
+  fn values(id: u32) -> Vec<Option<Value>> {
+    list_revisions("HistoryTestItem", &id.as_bytes())
+      .unwrap()
+      .into_iter()
+      .map(|revision| revision.value)
+      .collect()
+  }
+
+  #[tokio::test]
+  async fn test_list_and_prune() {
+    init_test_storage().await;
+    for n in 0..HISTORY_LIMIT as u32 + 3 {
+      Item { id: 1, n }.save_and_flush().unwrap();
+    }
+    let values = values(1);
+    assert_eq!(values.len(), HISTORY_LIMIT);
+    // Newest first; the oldest states, including the creation, were pruned
+    assert_eq!(
+      values.first(),
+      Some(&Some(json!({"id": 1, "n": HISTORY_LIMIT + 1})))
+    );
+    assert_eq!(values.last(), Some(&Some(json!({"id": 1, "n": 2}))));
+    let ids = list_revisions("HistoryTestItem", &1u32.as_bytes())
+      .unwrap()
+      .iter()
+      .map(|revision| revision.id)
+      .collect::<Vec<_>>();
+    assert!(ids.windows(2).all(|pair| pair[0] > pair[1]));
+  }
+
+  #[tokio::test]
+  async fn test_restore_removed_record() {
+    init_test_storage().await;
+    Item { id: 2, n: 5 }.save_and_flush().unwrap();
+    Item::remove_and_flush(&2).unwrap();
+    let removal = list_revisions("HistoryTestItem", &2u32.as_bytes()).unwrap()[0].clone();
+    assert_eq!(
+      (removal.operation, removal.value),
+      (ChangeOperation::Remove, Some(json!({"id": 2, "n": 5})))
+    );
+    appdata_cmd_restore_revision("HistoryTestItem", json!(2), removal.id)
+      .await
+      .unwrap();
+    assert_eq!(
+      <Item as AppEntity>::get_data(&2).unwrap(),
+      Some(Item { id: 2, n: 5 })
+    );
+    assert_eq!(values(2).len(), 3);
+    let err = appdata_cmd_restore_revision("HistoryTestItem", json!(2), 0)
+      .await
+      .unwrap_err();
+    assert_eq!(err.code, crate::storage::ErrorCode::NotFound);
+  }
+}
@@ -0,0 +1,209 @@
+use reindeer::AsBytes;
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{AppError, AppResult};
+
+/// Shape of the keys of a store, so the frontend knows how to render and send them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum KeyType {
+  /// Sent as a JSON number
+  Integer,
+  String,
+  /// Sent as a hyphenated string
+  Uuid,
+  /// Sent as a JSON array with one element per part
+  Composite {
+    parts: Vec<KeyType>,
+  },
+}
+
+/// A key usable by appdata stores: exchanged as JSON, stored with `AsBytes`.
+pub trait AppKey:
+  AsBytes + Clone + Ord + Serialize + DeserializeOwned + Send + Sync + 'static
+{
+  /// Only one key exists, so the store holds at most one record
+  const SINGLETON: bool = false;
+  fn key_type() -> KeyType;
+  /// The key after this one, for key allocation; `None` if keys are not sequential
+  fn successor(&self) -> Option<Self> {
+    None
+  }
+  /// The key for value `n` of a store's key sequence; `None` if keys are not sequential
+  fn from_sequence(_n: u64) -> Option<Self> {
+    None
+  }
+  /// Inverse of `from_sequence`, used to start a sequence after the existing records
+  fn to_sequence(&self) -> Option<u64> {
+    None
+  }
+  /// A fresh key for stores whose keys are random rather than sequential
+  fn random() -> Option<Self> {
+    None
+  }
+}
+
+impl AppKey for u32 {
+  fn key_type() -> KeyType {
+    KeyType::Integer
+  }
+  fn successor(&self) -> Option<Self> {
+    self.checked_add(1)
+  }
+  fn from_sequence(n: u64) -> Option<Self> {
+    n.try_into().ok()
+  }
+  fn to_sequence(&self) -> Option<u64> {
+    Some(*self as u64)
+  }
+}
+
+impl AppKey for u64 {
+  fn key_type() -> KeyType {
+    KeyType::Integer
+  }
+  fn successor(&self) -> Option<Self> {
+    self.checked_add(1)
+  }
+  fn from_sequence(n: u64) -> Option<Self> {
+    Some(n)
+  }
+  fn to_sequence(&self) -> Option<u64> {
+    Some(*self)
+  }
+}
+
+impl AppKey for String {
+  fn key_type() -> KeyType {
+    KeyType::String
+  }
+}
+
+impl<A: AppKey, B: AppKey> AppKey for (A, B)
+where
+  (A, B): AsBytes,
+{
+  fn key_type() -> KeyType {
+    KeyType::Composite {
+      parts: vec![A::key_type(), B::key_type()],
+    }
+  }
+}
+
+/// A UUID record key
+#[derive(
+  Debug,
+  Clone,
+  Copy,
+  Default,
+  PartialEq,
+  Eq,
+  PartialOrd,
+  Ord,
+  Hash,
+  Serialize,
+  Deserialize,
+  JsonSchema,
+)]
+#[serde(transparent)]
+pub struct UuidKey(#[schemars(with = "String", extend("format" = "uuid"))] pub uuid::Uuid);
+
+impl UuidKey {
+  pub fn new_v4() -> Self {
+    Self(uuid::Uuid::new_v4())
+  }
+}
+
+impl AsBytes for UuidKey {
+  fn as_bytes(&self) -> Vec<u8> {
+    self.0.as_bytes().to_vec()
+  }
+  fn from_bytes(bytes: &[u8]) -> Self {
+    Self(uuid::Uuid::from_slice(bytes).unwrap_or_default())
+  }
+}
+
+impl AppKey for UuidKey {
+  fn key_type() -> KeyType {
+    KeyType::Uuid
+  }
+  fn random() -> Option<Self> {
+    Some(Self::new_v4())
+  }
+}
+
+/// The key of singleton stores, which hold a single record.
+///
+/// The record is stored under the bytes of `0u32`, where `AppConfig` has always kept it, and any
+/// key sent by the frontend addresses it; it is sent back as `0`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SingletonKey;
+
+impl AsBytes for SingletonKey {
+  fn as_bytes(&self) -> Vec<u8> {
+    0u32.as_bytes()
+  }
+  fn from_bytes(_bytes: &[u8]) -> Self {
+    Self
+  }
+}
+
+impl Serialize for SingletonKey {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u32(0)
+  }
+}
+
+impl<'de> Deserialize<'de> for SingletonKey {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    serde::de::IgnoredAny::deserialize(deserializer).map(|_| Self)
+  }
+}
+
+impl AppKey for SingletonKey {
+  const SINGLETON: bool = true;
+  fn key_type() -> KeyType {
+    KeyType::Integer
+  }
+}
+
+/// Reads a key sent by the frontend.
+pub fn parse_key<K: AppKey>(key: &Value) -> AppResult<K> {
+  serde_json::from_value(key.clone())
+    .map_err(|e| AppError::invalid_argument(format!("Invalid key {}: {}", key, e)))
+}
+
+/// The JSON form of a key, as sent to the frontend.
+pub fn key_value<K: AppKey>(key: &K) -> AppResult<Value> {
+  Ok(serde_json::to_value(key)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn test_parse_key() {
+    assert_eq!(parse_key::<u32>(&json!(7)).unwrap(), 7);
+    assert!(parse_key::<u32>(&json!("7")).is_err());
+    assert_eq!(
+      parse_key::<(String, u32)>(&json!(["acme", 1])).unwrap(),
+      ("acme".to_owned(), 1)
+    );
+    let uuid = UuidKey::new_v4();
+    assert_eq!(
+      parse_key::<UuidKey>(&key_value(&uuid).unwrap()).unwrap(),
+      uuid
+    );
+    assert_eq!(parse_key::<SingletonKey>(&json!(0)).unwrap(), SingletonKey);
+    assert_eq!(key_value(&SingletonKey).unwrap(), json!(0));
+    assert_eq!(SingletonKey.as_bytes(), 0u32.as_bytes());
+    assert_eq!(
+      serde_json::to_value(<(String, u32)>::key_type()).unwrap(),
+      json!({"kind": "composite", "parts": [{"kind": "string"}, {"kind": "integer"}]})
+    );
+  }
+}
@@ -0,0 +1,285 @@
+use ::sled::transaction::{ConflictableTransactionResult, TransactionError};
+use ::sled::Transactional;
+use reindeer::Entity;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::sync::Mutex;
+
+use super::{db, flush_db, history_tree, AppError, AppResult, ErrorCode, Revision};
+
+/// One step of a store's upgrade path, from version `from` to `from + 1`
+pub struct Migration {
+  pub from: u32,
+  decode: fn(&[u8]) -> AppResult<Value>,
+  upgrade: fn(Value) -> AppResult<Value>,
+}
+
+impl Migration {
+  /// `Old` is the entity as it was at version `from`, needed to decode records still stored in
+  /// that layout; `upgrade` turns its JSON form into the layout of the next version.
+  pub fn new<Old: DeserializeOwned + Serialize>(
+    from: u32,
+    upgrade: fn(Value) -> AppResult<Value>,
+  ) -> Self {
+    Self {
+      from,
+      decode: decode_as::<Old>,
+      upgrade,
+    }
+  }
+}
+
+fn decode_as<T: DeserializeOwned + Serialize>(bytes: &[u8]) -> AppResult<Value> {
+  Ok(serde_json::to_value(reindeer::bincode_deserialize::<T>(
+    bytes,
+  )?)?)
+}
+
+/// Schema version of an entity and the migrations that bring older records up to it.
+///
+/// Records written before an entity had a version are version `0`.
+pub trait Versioned {
+  /// Bump together with a new migration whenever the stored layout changes
+  const VERSION: u32 = 0;
+  /// Upgrade the store on its first access instead of when it is registered
+  const LAZY_MIGRATION: bool = false;
+  /// One migration for every version in `0..VERSION`
+  fn migrations() -> Vec<Migration> {
+    vec![]
+  }
+}
+
+fn meta_tree() -> AppResult<::sled::Tree> {
+  Ok(db().open_tree("__meta")?)
+}
+
+fn version_key(store: &str) -> String {
+  format!("version/{}", store)
+}
+
+/// Schema version the records of a store are written in; `None` if it was never recorded.
+pub fn stored_version(store: &str) -> AppResult<Option<u32>> {
+  match meta_tree()?.get(version_key(store))? {
+    Some(bytes) => Ok(Some(u32::from_be_bytes(
+      bytes
+        .as_ref()
+        .try_into()
+        .map_err(|_| AppError::new(ErrorCode::DecodeError, "Corrupted schema version entry"))?,
+    ))),
+    None => Ok(None),
+  }
+}
+
+fn step(migrations: &[Migration], version: u32) -> AppResult<&Migration> {
+  migrations
+    .iter()
+    .find(|m| m.from == version)
+    .ok_or_else(|| AppError::internal(format!("No migration from version {}", version)))
+}
+
+/// Runs the upgrades of versions `from..to` over a record already in JSON form.
+fn upgrade_value(migrations: &[Migration], from: u32, to: u32, value: Value) -> AppResult<Value> {
+  (from..to).try_fold(value, |value, version| {
+    (step(migrations, version)?.upgrade)(value)
+  })
+}
+
+/// Decodes a record stored at version `from` and upgrades it to version `to`.
+fn upgrade_record(migrations: &[Migration], from: u32, to: u32, bytes: &[u8]) -> AppResult<Value> {
+  let value = (step(migrations, from)?.decode)(bytes)?;
+  upgrade_value(migrations, from, to, value)
+}
+
+/// Brings a record exported at version `from` up to `T::VERSION`.
+pub(super) fn upgrade_json<T: Entity + Versioned>(from: u32, value: Value) -> AppResult<Value> {
+  if from > T::VERSION {
+    return Err(AppError::invalid_argument(format!(
+      "Records were written by a newer version: store={}, version={}, supported version={}",
+      T::store_name(),
+      from,
+      T::VERSION
+    )));
+  }
+  upgrade_value(&T::migrations(), from, T::VERSION, value)
+}
+
+/// Reads a record of `T`'s store and upgrades it to `T::VERSION` in memory, leaving the store
+/// untouched even if it has not been migrated yet.
+pub(super) fn peek_record<T: Entity + Versioned>(key_bytes: &[u8]) -> AppResult<Option<Value>> {
+  let Some(bytes) = T::get_tree(db())?.get(key_bytes)? else {
+    return Ok(None);
+  };
+  let from = stored_version(T::store_name())?.unwrap_or(0);
+  if from == T::VERSION {
+    return decode_as::<T>(&bytes).map(Some);
+  }
+  if from > T::VERSION {
+    return Err(AppError::internal(format!(
+      "Store was written by a newer version: store={}, stored version={}, supported version={}",
+      T::store_name(),
+      from,
+      T::VERSION
+    )));
+  }
+  upgrade_record(&T::migrations(), from, T::VERSION, &bytes).map(Some)
+}
+
+/// Stores already checked in this process
+static MIGRATED: Mutex<Vec<&'static str>> = Mutex::new(vec![]);
+
+/// Upgrades the records of `T`'s store to `T::VERSION`, once per process.
+///
+/// Must run before a store is read or written; the write lock may be held, since migrations never
+/// take it.
+pub(super) fn ensure_migrated<T: Entity + Versioned>() -> AppResult<()> {
+  let mut migrated = MIGRATED.lock().unwrap_or_else(|e| e.into_inner());
+  if migrated.contains(&T::store_name()) {
+    return Ok(());
+  }
+  migrate_store::<T>()?;
+  migrated.push(T::store_name());
+  Ok(())
+}
+
+/// Rewrites every record and history entry of a store in one transaction, together with its new
+/// version, so an interrupted migration leaves the store untouched.
+fn migrate_store<T: Entity + Versioned>() -> AppResult<()> {
+  let store = T::store_name();
+  let data = T::get_tree(db())?;
+  let stored = stored_version(store)?;
+  let from = match stored {
+    Some(version) => version,
+    None if data.is_empty() => T::VERSION,
+    None => 0,
+  };
+  if from > T::VERSION {
+    return Err(AppError::internal(format!(
+      "Store was written by a newer version: store={}, stored version={}, supported version={}",
+      store,
+      from,
+      T::VERSION
+    )));
+  }
+  if from == T::VERSION {
+    if stored.is_none() {
+      meta_tree()?.insert(version_key(store), &T::VERSION.to_be_bytes())?;
+      flush_db()?;
+    }
+    return Ok(());
+  }
+
+  let migrations = T::migrations();
+  let mut records = Vec::new();
+  for entry in data.iter() {
+    let (key, bytes) = entry?;
+    let record = upgrade_record(&migrations, from, T::VERSION, &bytes)
+      .and_then(|value| Ok(serde_json::from_value::<T>(value)?))
+      .map_err(|mut e| {
+        e.message = format!(
+          "Failed to migrate record: store={}, key={:?}, {}",
+          store,
+          key.as_ref(),
+          e.message
+        );
+        e
+      })?;
+    records.push((key, reindeer::bincode_serialize(&record)?));
+  }
+  let history = history_tree(store)?;
+  let mut revisions = Vec::new();
+  for entry in history.iter() {
+    let (key, bytes) = entry?;
+    let mut revision: Revision = serde_json::from_slice(&bytes)?;
+    revision.value = revision
+      .value
+      .map(|value| upgrade_value(&migrations, from, T::VERSION, value))
+      .transpose()?;
+    revisions.push((key, serde_json::to_vec(&revision)?));
+  }
+
+  let version_key = version_key(store);
+  [data, history, meta_tree()?]
+    .as_slice()
+    .transaction(|txs| -> ConflictableTransactionResult<(), ()> {
+      for (key, bytes) in &records {
+        txs[0].insert(key.as_ref(), bytes.as_slice())?;
+      }
+      for (key, bytes) in &revisions {
+        txs[1].insert(key.as_ref(), bytes.as_slice())?;
+      }
+      txs[2].insert(version_key.as_bytes(), &T::VERSION.to_be_bytes())?;
+      Ok(())
+    })
+    .map_err(|e: TransactionError<()>| {
+      AppError::new(
+        ErrorCode::StorageIo,
+        format!("Migration transaction failed: {:?}", e),
+      )
+    })?;
+  flush_db()?;
+  log::info!(
+    "AppData migrated: id={}, from={}, to={}, records={}",
+    store,
+    from,
+    T::VERSION,
+    records.len()
+  );
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde::Deserialize;
+  use serde_json::json;
+
+  #[derive(Serialize, Deserialize)]
+  struct V0 {
+    id: u32,
+    name: String,
+  }
+
+  #[derive(Serialize, Deserialize)]
+  struct V1 {
+    id: u32,
+    full_name: String,
+  }
+
+  fn migrations() -> Vec<Migration> {
+    vec![
+      Migration::new::<V0>(0, |mut value| {
+        value["full_name"] = value["name"].take();
+        value.as_object_mut().unwrap().remove("name");
+        Ok(value)
+      }),
+      Migration::new::<V1>(1, |mut value| {
+        value["active"] = json!(true);
+        Ok(value)
+      }),
+    ]
+  }
+
+  #[test]
+  fn test_upgrade_record() {
+    let bytes = reindeer::bincode_serialize(&V0 {
+      id: 7,
+      name: "andeya".to_owned(),
+    })
+    .unwrap();
+    let value = upgrade_record(&migrations(), 0, 2, &bytes).unwrap();
+    assert_eq!(
+      value,
+      json!({"id": 7, "full_name": "andeya", "active": true})
+    );
+
+    let bytes = reindeer::bincode_serialize(&V1 {
+      id: 7,
+      full_name: "andeya".to_owned(),
+    })
+    .unwrap();
+    let value = upgrade_record(&migrations(), 1, 2, &bytes).unwrap();
+    assert_eq!(value["active"], json!(true));
+
+    assert!(upgrade_record(&migrations(), 0, 3, &bytes).is_err());
+  }
+}
@@ -1,14 +1,75 @@
 use std::path::PathBuf;
 
 mod appdata;
-mod rawentity;
+mod archive;
+mod batch;
+mod csvimport;
+mod descriptor;
+mod diff;
+mod dynamic;
+mod error;
+mod events;
+mod format;
+mod history;
+mod key;
+mod migration;
+mod patch;
+mod preview;
+mod query;
+mod revision;
+mod sequence;
+mod singleton;
 mod sled;
+mod trash;
+mod validate;
+mod write;
 
+/// Opens the database and registers every store collected with `register_appdata!` or
+/// `#[derive(AppData)]`, followed by the dynamic stores.
 pub async fn init(app_data_dir: PathBuf) -> anyhow::Result<()> {
   sled::init(app_data_dir)?;
+  register_collected().await?;
+  register_dynamic_stores().await?;
   Ok(())
 }
 
+/// Runs `init` once per test binary, on a database in a fresh temporary directory. Tests share
+/// it, so each test module uses stores of its own.
+#[cfg(test)]
+pub(crate) async fn init_test_storage() {
+  static INIT: tokio::sync::OnceCell<()> = tokio::sync::OnceCell::const_new();
+  INIT
+    .get_or_init(|| async {
+      let dir = std::env::temp_dir().join(format!("craft-gui-test-{}", std::process::id()));
+      let _ = std::fs::remove_dir_all(&dir);
+      init(dir).await.expect("Failed to initialize test storage");
+    })
+    .await;
+}
+
 pub use appdata::*;
-pub use rawentity::*;
+pub use appdata_derive::AppData;
+pub use archive::*;
+pub use batch::*;
+pub use csvimport::*;
+pub use descriptor::*;
+pub use diff::*;
+pub use dynamic::*;
+pub use error::*;
+pub use events::*;
+pub use format::*;
+pub use history::*;
+#[doc(hidden)]
+pub use inventory;
+pub use key::*;
+pub use migration::*;
+pub use patch::*;
+pub use preview::*;
+pub use query::*;
+pub use revision::*;
+pub use sequence::*;
+pub use singleton::*;
 use sled::{db, flush_db};
+pub use trash::*;
+pub use validate::*;
+use write::*;
@@ -0,0 +1,277 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::sync::Arc;
+
+use super::{
+  apply_locked, get_writable, notify, singleton_record, write_lock, AppData, AppError, AppResult,
+  BatchOp, EncodedRecord, ErrorCode, Filter, Record, StoreKind,
+};
+
+/// A partial update of a stored record
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "patch", rename_all = "camelCase")]
+pub enum RecordPatch {
+  /// RFC 6902 JSON Patch: a list of operations
+  Json(json_patch::Patch),
+  /// RFC 7386 JSON Merge Patch: an object whose fields replace those of the record, `null`
+  /// removing them
+  Merge(Value),
+}
+
+impl RecordPatch {
+  pub fn apply(&self, value: &mut Value) -> AppResult<()> {
+    match self {
+      Self::Json(patch) => json_patch::patch(value, patch).map_err(|e| {
+        let code = match e.kind {
+          json_patch::PatchErrorKind::TestFailed => ErrorCode::Conflict,
+          _ => ErrorCode::InvalidArgument,
+        };
+        AppError::new(code, format!("Failed to apply patch: {}", e)).with_path(e.path.to_string())
+      }),
+      Self::Merge(patch) => {
+        json_patch::merge(value, patch);
+        Ok(())
+      }
+    }
+  }
+}
+
+/// Validates a patched record, which must keep the key `key`.
+fn encode_patched(
+  appdata: &dyn AppData,
+  key_bytes: &[u8],
+  key: &Value,
+  data: &[u8],
+) -> AppResult<EncodedRecord> {
+  let encoded = appdata.encode_data(data)?;
+  if encoded.key_bytes != key_bytes {
+    return Err(AppError::invalid_argument(format!(
+      "A patch cannot change the key of a record: key={}, new key={}",
+      key, encoded.key
+    )));
+  }
+  Ok(encoded)
+}
+
+/// Applies `patch` to the stored record and saves the result, returning the new record.
+///
+/// The record is saved only if it is still at the revision it was patched from, so concurrent
+/// writes are never overwritten; `expected_revision` additionally pins the revision the caller
+/// read. A patch may not change the key of the record. Singleton records are patched from their
+/// default if none was saved.
+pub fn patch_record(
+  appdata: &dyn AppData,
+  key: &Value,
+  patch: &RecordPatch,
+  expected_revision: Option<u64>,
+) -> AppResult<Record> {
+  let record = match appdata.get_record(key)? {
+    Some(record) => record,
+    None if appdata.kind() == StoreKind::Singleton => singleton_record(appdata)?,
+    None => {
+      return Err(AppError::not_found(format!(
+        "Record not found: key={}",
+        key
+      )))
+    }
+  };
+  if expected_revision.is_some_and(|expected| expected != record.revision) {
+    return Err(AppError::new(
+      ErrorCode::Conflict,
+      format!(
+        "Record was changed: key={}, expected revision={}, current revision={}",
+        key,
+        expected_revision.unwrap_or_default(),
+        record.revision
+      ),
+    ));
+  }
+  let mut value: Value = serde_json::from_slice(&record.data)?;
+  patch.apply(&mut value)?;
+  let data = serde_json::to_vec(&value)?;
+  let encoded = encode_patched(appdata, &appdata.encode_key(key)?, key, &data)?;
+  let revision = appdata.save_checked(&data, Some(record.revision))?;
+  Ok(Record {
+    key: encoded.key,
+    data: serde_json::to_vec(&encoded.value)?,
+    revision,
+  })
+}
+
+/// Applies a JSON Patch or Merge Patch to a stored record, validates and saves it, and returns
+/// the updated record.
+#[tauri::command]
+pub async fn appdata_cmd_patch_data(
+  schema_id: &str,
+  key: Value,
+  patch: RecordPatch,
+  expected_revision: Option<u64>,
+) -> AppResult<Record> {
+  let appdata = get_writable(schema_id).await?;
+  patch_record(appdata.as_ref(), &key, &patch, expected_revision)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkUpdateResult {
+  /// Number of records matching the filter
+  pub matched: usize,
+  /// Keys of the records the patch changed, in key order
+  pub keys: Vec<Value>,
+  /// `false` for a dry run
+  pub applied: bool,
+}
+
+/// Applies `patch` to every record of a store matching `filter`, in one transaction.
+///
+/// Every patched record is validated before anything is written, so one failing record aborts the
+/// whole update. Records the patch leaves unchanged are not rewritten. With `dry_run` the records
+/// are patched and validated but not saved.
+pub fn bulk_update(
+  appdata: Arc<dyn AppData>,
+  filter: &Filter,
+  patch: &RecordPatch,
+  dry_run: bool,
+) -> AppResult<BulkUpdateResult> {
+  let guard = write_lock();
+  let mut matched = 0;
+  let mut keys = Vec::new();
+  let mut ops = Vec::new();
+  for value in appdata.all_values()? {
+    if !filter.matches(&value) {
+      continue;
+    }
+    matched += 1;
+    let original = appdata.encode_data(&serde_json::to_vec(&value)?)?;
+    let mut patched = value;
+    let encoded = patch
+      .apply(&mut patched)
+      .and_then(|()| Ok(serde_json::to_vec(&patched)?))
+      .and_then(|data| encode_patched(appdata.as_ref(), &original.key_bytes, &original.key, &data))
+      .map_err(|mut e| {
+        e.message = format!("Record {} failed: {}", original.key, e.message);
+        e
+      })?;
+    if encoded.value == original.value {
+      continue;
+    }
+    keys.push(original.key);
+    ops.push(BatchOp::Save {
+      schema_id: appdata.id().to_owned(),
+      data: serde_json::to_vec(&encoded.value)?,
+      expected_revision: None,
+    });
+  }
+  let events = if dry_run {
+    vec![]
+  } else {
+    apply_locked(ops.iter().map(|op| (op, appdata.clone())))?
+  };
+  drop(guard);
+
+  for event in events {
+    notify(event);
+  }
+  Ok(BulkUpdateResult {
+    matched,
+    keys,
+    applied: !dry_run,
+  })
+}
+
+/// Applies a patch to every record of a store matching `filter`, in one transaction, and returns
+/// the keys of the changed records. With `dry_run` nothing is saved.
+#[tauri::command]
+pub async fn appdata_cmd_bulk_update(
+  schema_id: &str,
+  filter: Filter,
+  patch: RecordPatch,
+  dry_run: bool,
+) -> AppResult<BulkUpdateResult> {
+  bulk_update(get_writable(schema_id).await?, &filter, &patch, dry_run)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::storage::{get, init_test_storage, AppEntity};
+  use schemars::JsonSchema;
+  use serde_json::json;
+
+  #[derive(
+    Debug, Default, Clone, PartialEq, Serialize, Deserialize, JsonSchema, crate::AppData,
+  )]
+  #[appdata(store = "PatchTestItem", key = id)]
+  struct Item {
+    id: u32,
+    group: String,
+    n: u32,
+  }
+
+  #[test]
+  fn test_apply_patch() {
+    let mut value = json!({"name": "andeya", "age": 30, "is_active": true});
+    let patch: RecordPatch = serde_json::from_value(json!({
+      "kind": "json",
+      "patch": [
+        {"op": "test", "path": "/age", "value": 30},
+        {"op": "replace", "path": "/is_active", "value": false}
+      ]
+    }))
+    .unwrap();
+    patch.apply(&mut value).unwrap();
+    assert_eq!(value["is_active"], json!(false));
+    assert_eq!(
+      patch.apply(&mut json!({"age": 31})).unwrap_err().code,
+      ErrorCode::Conflict
+    );
+
+    let patch = RecordPatch::Merge(json!({"age": null, "name": "lee"}));
+    patch.apply(&mut value).unwrap();
+    assert_eq!(value, json!({"name": "lee", "is_active": false}));
+  }
+
+  #[tokio::test]
+  async fn test_bulk_update() {
+    init_test_storage().await;
+    for (id, group, n) in [(1, "a", 1), (2, "a", 5), (3, "b", 1)] {
+      AppEntity::save_and_flush(&Item {
+        id,
+        group: group.to_owned(),
+        n,
+      })
+      .unwrap();
+    }
+    let revisions = || {
+      [1, 2, 3]
+        .map(|id| <Item as AppEntity>::get_revision(&id).unwrap())
+        .to_vec()
+    };
+    let before = revisions();
+    let appdata = get("PatchTestItem").await.unwrap();
+    let filter: Filter =
+      serde_json::from_value(json!({"op": "eq", "path": "group", "value": "a"})).unwrap();
+    let patch = RecordPatch::Merge(json!({"n": 5}));
+
+    let dry_run = bulk_update(appdata.clone(), &filter, &patch, true).unwrap();
+    // Record 2 matches but already has n = 5
+    assert_eq!(
+      (dry_run.matched, dry_run.keys, dry_run.applied),
+      (2, vec![json!(1)], false)
+    );
+    assert_eq!(<Item as AppEntity>::get_data(&1).unwrap().unwrap().n, 1);
+    assert_eq!(revisions(), before);
+
+    let result = bulk_update(appdata, &filter, &patch, false).unwrap();
+    assert_eq!(
+      (result.matched, result.keys, result.applied),
+      (2, vec![json!(1)], true)
+    );
+    let ns = [1, 2, 3].map(|id| <Item as AppEntity>::get_data(&id).unwrap().unwrap().n);
+    assert_eq!(ns, [5, 5, 1]);
+    let after = revisions();
+    assert_ne!(after[0], before[0]);
+    assert_eq!(after[1..], before[1..]);
+  }
+}
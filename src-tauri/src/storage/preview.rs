@@ -0,0 +1,204 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::{
+  diff_values, get_ok, label_changes, read_records, AppData, AppResult, FieldChange, FieldError,
+};
+
+/// What importing a record would do to the store
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PreviewStatus {
+  New,
+  Changed,
+  Unchanged,
+  /// The record does not match the schema or cannot be upgraded, and would fail the import
+  Invalid,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewRecord {
+  /// Position of the record in the file
+  pub index: usize,
+  /// `None` if the record could not be decoded
+  pub key: Option<Value>,
+  pub status: PreviewStatus,
+  /// Differences from the stored record, for `Changed`
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub changes: Vec<FieldChange>,
+  /// Schema violations, for `Invalid`
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub errors: Vec<FieldError>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportPreview {
+  pub new: usize,
+  pub changed: usize,
+  pub unchanged: usize,
+  pub invalid: usize,
+  pub records: Vec<PreviewRecord>,
+}
+
+/// Compares the records of an import file with a store, without writing anything.
+///
+/// Records are upgraded from schema version `version` first. A key repeated within the file is
+/// compared with its earlier occurrence, which the import would overwrite.
+pub fn preview_import(
+  appdata: &dyn AppData,
+  version: u32,
+  records: Vec<Value>,
+) -> AppResult<ImportPreview> {
+  let mut preview = ImportPreview::default();
+  let mut seen: HashMap<Vec<u8>, Value> = HashMap::new();
+  let schema = appdata.schema();
+  for (index, value) in records.into_iter().enumerate() {
+    let mut record = PreviewRecord {
+      index,
+      key: None,
+      status: PreviewStatus::Invalid,
+      changes: vec![],
+      errors: vec![],
+    };
+    let encoded = appdata
+      .upgrade(version, value)
+      .and_then(|value| appdata.encode_data(&serde_json::to_vec(&value)?));
+    let encoded = match encoded {
+      Ok(encoded) => encoded,
+      Err(e) => {
+        record.errors = if e.fields.is_empty() {
+          vec![FieldError {
+            path: e.path.unwrap_or_default(),
+            message: e.message,
+          }]
+        } else {
+          e.fields
+        };
+        preview.invalid += 1;
+        preview.records.push(record);
+        continue;
+      }
+    };
+    record.key = Some(encoded.key.clone());
+    let before = match seen.get(&encoded.key_bytes) {
+      Some(before) => Some(before.clone()),
+      // Read without migrating, so the preview never writes
+      None => appdata.peek_data(&encoded.key)?,
+    };
+    match before {
+      None => {
+        record.status = PreviewStatus::New;
+        preview.new += 1;
+      }
+      Some(before) => {
+        record.changes = diff_values(&before, &encoded.value);
+        label_changes(schema.as_value(), &mut record.changes);
+        if record.changes.is_empty() {
+          record.status = PreviewStatus::Unchanged;
+          preview.unchanged += 1;
+        } else {
+          record.status = PreviewStatus::Changed;
+          preview.changed += 1;
+        }
+      }
+    }
+    seen.insert(encoded.key_bytes, encoded.value);
+    preview.records.push(record);
+  }
+  Ok(preview)
+}
+
+/// Previews importing a file of records into a store, in the given format or as JSON, such as
+/// written by `export_data`.
+#[tauri::command]
+pub async fn appdata_cmd_preview_import(
+  schema_id: &str,
+  path: String,
+  format: Option<String>,
+) -> AppResult<ImportPreview> {
+  let appdata = get_ok(schema_id).await?;
+  let store = read_records(format.as_deref().unwrap_or("json"), appdata.as_ref(), &path)?;
+  preview_import(appdata.as_ref(), store.version, store.records)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::storage::{db, get, init_test_storage, stored_version, Migration};
+  use reindeer::AsBytes;
+  use schemars::JsonSchema;
+  use serde_json::json;
+
+  #[derive(Debug, Default, Clone, Serialize, Deserialize, JsonSchema, crate::AppData)]
+  #[appdata(
+    store = "PreviewTestItem",
+    key = id,
+    version = 1,
+    migrations = item_migrations,
+    lazy_migration
+  )]
+  struct Item {
+    id: u32,
+    name: String,
+    tag: String,
+  }
+
+  #[derive(Serialize, Deserialize)]
+  struct ItemV0 {
+    id: u32,
+    name: String,
+  }
+
+  fn item_migrations() -> Vec<Migration> {
+    vec![Migration::new::<ItemV0>(0, |mut value| {
+      value["tag"] = json!("none");
+      Ok(value)
+    })]
+  }
+
+  #[tokio::test]
+  async fn test_preview_does_not_migrate() {
+    init_test_storage().await;
+    let tree = db().open_tree("PreviewTestItem").unwrap();
+    let old = reindeer::bincode_serialize(&ItemV0 {
+      id: 1,
+      name: "a".to_owned(),
+    })
+    .unwrap();
+    tree.insert(1u32.as_bytes(), old.as_slice()).unwrap();
+
+    let appdata = get("PreviewTestItem").await.unwrap();
+    let preview = preview_import(
+      appdata.as_ref(),
+      1,
+      vec![
+        json!({"id": 1, "name": "b", "tag": "none"}),
+        json!({"id": 2, "name": "c", "tag": "x"}),
+      ],
+    )
+    .unwrap();
+    assert_eq!((preview.changed, preview.new), (1, 1));
+    assert_eq!(preview.records[0].changes[0].path, "/name");
+
+    // The store is still at version 0
+    assert_eq!(stored_version("PreviewTestItem").unwrap(), None);
+    assert_eq!(
+      tree.get(1u32.as_bytes()).unwrap().as_deref(),
+      Some(old.as_slice())
+    );
+  }
+
+  #[tokio::test]
+  async fn test_preview_upgrades_records() {
+    init_test_storage().await;
+    let appdata = get("PreviewTestItem").await.unwrap();
+    // Exported before `tag` was added
+    let preview = preview_import(appdata.as_ref(), 0, vec![json!({"id": 7, "name": "d"})]).unwrap();
+    assert_eq!(preview.new, 1);
+    let preview = preview_import(appdata.as_ref(), 2, vec![json!({"id": 7, "name": "d"})]).unwrap();
+    assert_eq!(preview.invalid, 1);
+  }
+}
@@ -0,0 +1,295 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+
+/// Predicate over a JSON record.
+///
+/// Paths are dot separated property names (`features.max_concurrent`); numeric segments index
+/// into arrays (`tags.0`) and the empty path addresses the record itself.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum Filter {
+  Eq {
+    path: String,
+    value: Value,
+  },
+  Ne {
+    path: String,
+    value: Value,
+  },
+  Lt {
+    path: String,
+    value: Value,
+  },
+  Gt {
+    path: String,
+    value: Value,
+  },
+  /// Substring of a string, element of an array or key of an object
+  Contains {
+    path: String,
+    value: Value,
+  },
+  In {
+    path: String,
+    values: Vec<Value>,
+  },
+  And {
+    filters: Vec<Filter>,
+  },
+  Or {
+    filters: Vec<Filter>,
+  },
+  Not {
+    filter: Box<Filter>,
+  },
+}
+
+impl Filter {
+  pub fn matches(&self, record: &Value) -> bool {
+    match self {
+      Filter::Eq { path, value } => lookup(record, path).is_some_and(|v| json_eq(v, value)),
+      Filter::Ne { path, value } => !lookup(record, path).is_some_and(|v| json_eq(v, value)),
+      Filter::Lt { path, value } => {
+        lookup(record, path).is_some_and(|v| compare_scalar(v, value) == Some(Ordering::Less))
+      }
+      Filter::Gt { path, value } => {
+        lookup(record, path).is_some_and(|v| compare_scalar(v, value) == Some(Ordering::Greater))
+      }
+      Filter::Contains { path, value } => match (lookup(record, path), value) {
+        (Some(Value::String(s)), Value::String(needle)) => s.contains(needle.as_str()),
+        (Some(Value::Array(items)), _) => items.iter().any(|item| json_eq(item, value)),
+        (Some(Value::Object(map)), Value::String(key)) => map.contains_key(key),
+        _ => false,
+      },
+      Filter::In { path, values } => {
+        lookup(record, path).is_some_and(|v| values.iter().any(|value| json_eq(v, value)))
+      }
+      Filter::And { filters } => filters.iter().all(|f| f.matches(record)),
+      Filter::Or { filters } => filters.iter().any(|f| f.matches(record)),
+      Filter::Not { filter } => !filter.matches(record),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SortKey {
+  pub path: String,
+  #[serde(default)]
+  pub descending: bool,
+}
+
+/// Filter, sort and projection applied to every record of a store
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Query {
+  #[serde(default)]
+  pub filter: Option<Filter>,
+  /// Sort keys in priority order; records keep key order otherwise
+  #[serde(default)]
+  pub sort: Vec<SortKey>,
+  /// Paths to keep in each returned record; `None` returns whole records
+  #[serde(default)]
+  pub fields: Option<Vec<String>>,
+  #[serde(default)]
+  pub offset: usize,
+  #[serde(default)]
+  pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryResult<T> {
+  pub items: Vec<T>,
+  /// Number of matching records before `offset` and `limit` are applied
+  pub total: usize,
+}
+
+impl Query {
+  /// Runs the query over records that are already in key order.
+  pub fn apply(&self, records: Vec<Value>) -> QueryResult<Value> {
+    let mut matched = records
+      .into_iter()
+      .filter(|record| self.filter.as_ref().is_none_or(|f| f.matches(record)))
+      .collect::<Vec<_>>();
+    if !self.sort.is_empty() {
+      matched.sort_by(|a, b| {
+        self
+          .sort
+          .iter()
+          .map(|key| {
+            let ord = compare_total(lookup(a, &key.path), lookup(b, &key.path));
+            if key.descending {
+              ord.reverse()
+            } else {
+              ord
+            }
+          })
+          .find(|ord| ord.is_ne())
+          .unwrap_or(Ordering::Equal)
+      });
+    }
+    let total = matched.len();
+    let items = matched
+      .into_iter()
+      .skip(self.offset)
+      .take(self.limit.unwrap_or(usize::MAX))
+      .map(|record| match &self.fields {
+        Some(fields) => project(&record, fields),
+        None => record,
+      })
+      .collect();
+    QueryResult { items, total }
+  }
+}
+
+pub fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+  if path.is_empty() {
+    return Some(value);
+  }
+  path
+    .split('.')
+    .try_fold(value, |current, segment| match current {
+      Value::Object(map) => map.get(segment),
+      Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+      _ => None,
+    })
+}
+
+/// Builds a record containing only the given paths, preserving their nesting.
+pub fn project(value: &Value, fields: &[String]) -> Value {
+  let mut out = Value::Object(Map::new());
+  for field in fields {
+    let Some(found) = lookup(value, field) else {
+      continue;
+    };
+    let mut target = &mut out;
+    let mut segments = field.split('.').peekable();
+    while let Some(segment) = segments.next() {
+      let Value::Object(map) = target else {
+        break;
+      };
+      if segments.peek().is_none() {
+        map.insert(segment.to_string(), found.clone());
+        break;
+      }
+      target = map
+        .entry(segment.to_string())
+        .or_insert_with(|| Value::Object(Map::new()));
+    }
+  }
+  out
+}
+
+/// Equality that treats `1` and `1.0` as the same number.
+fn json_eq(a: &Value, b: &Value) -> bool {
+  match (a, b) {
+    (Value::Number(x), Value::Number(y)) => x.as_f64() == y.as_f64(),
+    _ => a == b,
+  }
+}
+
+fn compare_scalar(a: &Value, b: &Value) -> Option<Ordering> {
+  match (a, b) {
+    (Value::Number(x), Value::Number(y)) => x.as_f64()?.partial_cmp(&y.as_f64()?),
+    (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+    (Value::Bool(x), Value::Bool(y)) => Some(x.cmp(y)),
+    _ => None,
+  }
+}
+
+/// Total order used for sorting: missing < null < bool < number < string < array < object.
+fn compare_total(a: Option<&Value>, b: Option<&Value>) -> Ordering {
+  fn rank(v: Option<&Value>) -> u8 {
+    match v {
+      None => 0,
+      Some(Value::Null) => 1,
+      Some(Value::Bool(_)) => 2,
+      Some(Value::Number(_)) => 3,
+      Some(Value::String(_)) => 4,
+      Some(Value::Array(_)) => 5,
+      Some(Value::Object(_)) => 6,
+    }
+  }
+  match (a, b) {
+    (Some(x), Some(y)) => compare_scalar(x, y).unwrap_or_else(|| rank(a).cmp(&rank(b))),
+    _ => rank(a).cmp(&rank(b)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn products() -> Vec<Value> {
+    vec![
+      json!({"id": 1, "name": "a", "price": 120.0, "category": "tool", "in_stock": true}),
+      json!({"id": 2, "name": "b", "price": 40.0, "category": "toy", "in_stock": true}),
+      json!({"id": 3, "name": "c", "price": 10.5, "category": "tool", "in_stock": false}),
+      json!({"id": 4, "name": "d", "price": 99, "category": "book", "in_stock": true}),
+    ]
+  }
+
+  #[test]
+  fn test_filter_deserialize_and_match() {
+    let filter: Filter = serde_json::from_value(json!({
+      "op": "and",
+      "filters": [
+        {"op": "eq", "path": "in_stock", "value": true},
+        {"op": "lt", "path": "price", "value": 100},
+      ]
+    }))
+    .unwrap();
+    let ids = products()
+      .iter()
+      .filter(|p| filter.matches(p))
+      .map(|p| p["id"].as_u64().unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(ids, vec![2, 4]);
+  }
+
+  #[test]
+  fn test_query_sort_project_and_page() {
+    let query = Query {
+      filter: Some(Filter::Not {
+        filter: Box::new(Filter::In {
+          path: "category".into(),
+          values: vec![json!("book")],
+        }),
+      }),
+      sort: vec![SortKey {
+        path: "price".into(),
+        descending: true,
+      }],
+      fields: Some(vec!["id".into(), "name".into()]),
+      offset: 1,
+      limit: Some(1),
+    };
+    let result = query.apply(products());
+    assert_eq!(result.total, 3);
+    assert_eq!(result.items, vec![json!({"id": 2, "name": "b"})]);
+  }
+
+  #[test]
+  fn test_lookup_and_contains() {
+    let record = json!({"tags": ["x", "y"], "nested": {"name": "craft-gui"}});
+    assert_eq!(lookup(&record, "tags.1"), Some(&json!("y")));
+    assert_eq!(lookup(&record, "nested.missing"), None);
+    assert!(Filter::Contains {
+      path: "nested.name".into(),
+      value: json!("gui"),
+    }
+    .matches(&record));
+    assert!(Filter::Contains {
+      path: "tags".into(),
+      value: json!("x"),
+    }
+    .matches(&record));
+    assert_eq!(
+      project(&record, &["nested.name".into()]),
+      json!({"nested": {"name": "craft-gui"}})
+    );
+  }
+}
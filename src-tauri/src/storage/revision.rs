@@ -0,0 +1,103 @@
+use std::sync::{Mutex, MutexGuard};
+
+use super::{db, AppError, AppResult, ErrorCode};
+
+/// Serializes revision checks with the writes they guard.
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+pub(super) fn write_lock() -> MutexGuard<'static, ()> {
+  WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub(super) fn revision_tree(store: &str) -> AppResult<::sled::Tree> {
+  Ok(db().open_tree(format!("__revisions/{}", store))?)
+}
+
+/// Current revision of a record; `0` if it does not exist or was never saved through appdata.
+pub fn current_revision(store: &str, key_bytes: &[u8]) -> AppResult<u64> {
+  Ok(match revision_tree(store)?.get(key_bytes)? {
+    Some(bytes) => u64::from_be_bytes(
+      bytes
+        .as_ref()
+        .try_into()
+        .map_err(|_| AppError::new(ErrorCode::DecodeError, "Corrupted revision entry"))?,
+    ),
+    None => 0,
+  })
+}
+
+/// Fails with `Conflict` unless the record is still at `expected`; `None` skips the check.
+pub(super) fn check_revision(
+  store: &str,
+  key_bytes: &[u8],
+  expected: Option<u64>,
+) -> AppResult<()> {
+  let Some(expected) = expected else {
+    return Ok(());
+  };
+  let current = current_revision(store, key_bytes)?;
+  if current != expected {
+    return Err(AppError::new(
+      ErrorCode::Conflict,
+      format!(
+        "Record changed since it was read: store={}, expected revision={}, current revision={}",
+        store, expected, current
+      ),
+    ));
+  }
+  Ok(())
+}
+
+/// A new revision number, never handed out before.
+pub(super) fn next_revision() -> AppResult<u64> {
+  // sled may hand out 0 first, which means "no revision" here
+  Ok(db().generate_id()? + 1)
+}
+
+/// Forgets every revision of a store, e.g. after an import replaced records wholesale.
+pub(super) fn clear_revisions(store: &str) -> AppResult<()> {
+  Ok(revision_tree(store)?.clear()?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::storage::init_test_storage;
+
+  const STORE: &str = "RevisionTestItem";
+
+  fn conflict(key: &[u8], expected: u64) -> bool {
+    check_revision(STORE, key, Some(expected)).map_err(|e| e.code) == Err(ErrorCode::Conflict)
+  }
+
+  fn bump(key: &[u8]) -> u64 {
+    let revision = next_revision().unwrap();
+    revision_tree(STORE)
+      .unwrap()
+      .insert(key, &revision.to_be_bytes())
+      .unwrap();
+    revision
+  }
+
+  #[tokio::test]
+  async fn test_check_revision() {
+    init_test_storage().await;
+    // A missing record is at revision 0
+    assert_eq!(current_revision(STORE, b"a").unwrap(), 0);
+    assert!(check_revision(STORE, b"a", Some(0)).is_ok());
+    assert!(conflict(b"a", 1));
+
+    let first = bump(b"a");
+    assert!(first > 0);
+    assert!(check_revision(STORE, b"a", Some(first)).is_ok());
+    assert!(conflict(b"a", 0));
+    let second = bump(b"a");
+    assert!(second > first);
+    assert!(conflict(b"a", first));
+    assert!(check_revision(STORE, b"a", None).is_ok());
+
+    revision_tree(STORE).unwrap().remove(b"a").unwrap();
+    assert!(check_revision(STORE, b"a", Some(0)).is_ok());
+    assert!(conflict(b"a", second));
+  }
+}
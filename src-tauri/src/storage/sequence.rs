@@ -0,0 +1,150 @@
+use reindeer::Entity;
+
+use super::{db, AppError, AppKey, AppResult, KeyType};
+
+fn sequence_tree() -> AppResult<::sled::Tree> {
+  Ok(db().open_tree("__sequences")?)
+}
+
+/// Advances the key sequence of a store and returns the new value.
+///
+/// A new sequence starts after `floor`, which is only evaluated the first time. The increment is
+/// atomic, so concurrent callers never get the same value.
+pub fn next_sequence(store: &str, floor: impl FnOnce() -> AppResult<u64>) -> AppResult<u64> {
+  let tree = sequence_tree()?;
+  let floor = if tree.contains_key(store)? {
+    0
+  } else {
+    floor()?
+  };
+  let mut exhausted = false;
+  let next = tree
+    .update_and_fetch(store, |old| {
+      let current = old
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u64::from_be_bytes)
+        .unwrap_or(0)
+        .max(floor);
+      // Left as is once exhausted, so every later call fails too
+      let next = current.checked_add(1);
+      exhausted = next.is_none();
+      Some(next.unwrap_or(current).to_be_bytes().to_vec())
+    })?
+    .ok_or_else(|| AppError::internal("Key sequence vanished"))?;
+  if exhausted {
+    return Err(AppError::internal(format!(
+      "Key sequence exhausted: store={}",
+      store
+    )));
+  }
+  Ok(u64::from_be_bytes(next.as_ref().try_into().map_err(
+    |_| AppError::internal("Corrupted key sequence entry"),
+  )?))
+}
+
+/// Forgets the key sequence of a store, e.g. when the store is dropped.
+pub(super) fn clear_sequence(store: &str) -> AppResult<()> {
+  sequence_tree()?.remove(store)?;
+  Ok(())
+}
+
+/// Picks an unused key for a new record of `T`, from its sequence or at random.
+///
+/// Callers must hold the write lock until the record is saved.
+pub(super) fn allocate_key<T: Entity<Key: AppKey>>() -> AppResult<T::Key> {
+  allocate_key_in(T::store_name(), &T::get_tree(db())?)
+}
+
+/// `allocate_key` for a store backed by `tree`.
+pub(super) fn allocate_key_in<K: AppKey>(store: &str, tree: &::sled::Tree) -> AppResult<K> {
+  if K::random().is_none() && K::from_sequence(0).is_none() {
+    return Err(AppError::invalid_argument(format!(
+      "Keys of store {} cannot be generated",
+      store
+    )));
+  }
+  loop {
+    let key = match K::random() {
+      Some(key) => key,
+      None => {
+        let n = next_sequence(store, || {
+          let mut max = 0;
+          for key in tree.iter().keys() {
+            if let Some(n) = K::from_bytes(&key?).to_sequence() {
+              max = max.max(n);
+            }
+          }
+          Ok(max)
+        })?;
+        K::from_sequence(n)
+          .ok_or_else(|| AppError::internal(format!("Key sequence exhausted: store={}", store)))?
+      }
+    };
+    // Records saved with an explicit key may already use it
+    if !tree.contains_key(key.as_bytes())? {
+      return Ok(key);
+    }
+  }
+}
+
+/// First key from `start` on that `tree` does not hold; fails for keys that are not sequential.
+pub(super) fn next_free_key<K: AppKey>(store: &str, tree: &::sled::Tree, start: K) -> AppResult<K> {
+  let mut key = start;
+  while tree.contains_key(key.as_bytes())? {
+    key = key.successor().ok_or_else(|| match K::key_type() {
+      KeyType::Integer => AppError::internal("Key overflow"),
+      _ => AppError::invalid_argument(format!("Keys of store {} are not sequential", store)),
+    })?;
+  }
+  Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::storage::{init_test_storage, ErrorCode};
+  use reindeer::AsBytes;
+
+  #[tokio::test]
+  async fn test_next_sequence() {
+    init_test_storage().await;
+    assert_eq!(next_sequence("SequenceTestA", || Ok(5)).unwrap(), 6);
+    // The floor only seeds a new sequence
+    assert_eq!(
+      next_sequence("SequenceTestA", || panic!("floor evaluated again")).unwrap(),
+      7
+    );
+  }
+
+  #[tokio::test]
+  async fn test_allocate_key_in() {
+    init_test_storage().await;
+    let tree = db().open_tree("SequenceTestB").unwrap();
+    for key in [3u32, 9] {
+      tree.insert(key.as_bytes(), vec![]).unwrap();
+    }
+    assert_eq!(allocate_key_in::<u32>("SequenceTestB", &tree).unwrap(), 10);
+    assert_eq!(allocate_key_in::<u32>("SequenceTestB", &tree).unwrap(), 11);
+    // Skips a key saved explicitly meanwhile
+    tree.insert(12u32.as_bytes(), vec![]).unwrap();
+    assert_eq!(allocate_key_in::<u32>("SequenceTestB", &tree).unwrap(), 13);
+
+    let tree = db().open_tree("SequenceTestC").unwrap();
+    tree.insert(u32::MAX.as_bytes(), vec![]).unwrap();
+    let err = allocate_key_in::<u32>("SequenceTestC", &tree).unwrap_err();
+    assert_eq!(err.code, ErrorCode::Internal);
+    assert!(err.message.starts_with("Key sequence exhausted"));
+
+    // The sequence of u64 keys ends at the largest key rather than wrapping around
+    let tree = db().open_tree("SequenceTestE").unwrap();
+    tree.insert(u64::MAX.as_bytes(), vec![]).unwrap();
+    for _ in 0..2 {
+      let err = allocate_key_in::<u64>("SequenceTestE", &tree).unwrap_err();
+      assert_eq!(err.code, ErrorCode::Internal);
+      assert!(err.message.starts_with("Key sequence exhausted"));
+    }
+
+    let err = allocate_key_in::<String>("SequenceTestD", &tree).unwrap_err();
+    assert_eq!(err.code, ErrorCode::InvalidArgument);
+  }
+}
@@ -0,0 +1,163 @@
+use reindeer::Entity;
+
+use super::{
+  ensure_migrated, get_ok, get_writable, key_value, notify, patch_record, save_locked, write_lock,
+  AppData, AppEntity, AppError, AppResult, Record, RecordPatch, SingletonKey, StoreKind, Versioned,
+};
+
+/// A store holding a single record, such as `AppConfig`: declare it by keying the entity with
+/// `SingletonKey`.
+///
+/// The record reads as its default until it is first saved.
+pub trait AppSingleton: AppEntity + Entity<Key = SingletonKey> + Default {
+  /// The stored record, or the default if none was saved.
+  fn get_or_default() -> AppResult<Self> {
+    Ok(Self::get_data(&SingletonKey)?.unwrap_or_default())
+  }
+  /// Replaces the stored record with the default.
+  fn reset() -> AppResult<Self> {
+    let data = Self::default();
+    data.save_and_flush()?;
+    Ok(data)
+  }
+  /// Applies `f` to the stored record, or to the default, and saves the result without letting
+  /// another write slip in between.
+  fn update(f: impl FnOnce(&mut Self)) -> AppResult<Self>;
+}
+
+impl<T: AppEntity + Entity<Key = SingletonKey> + Versioned + Default> AppSingleton for T {
+  fn update(f: impl FnOnce(&mut Self)) -> AppResult<Self> {
+    ensure_migrated::<T>()?;
+    let guard = write_lock();
+    let mut data = T::get_data(&SingletonKey)?.unwrap_or_default();
+    f(&mut data);
+    let (_, event) = save_locked(&data, None)?;
+    drop(guard);
+    notify(event);
+    Ok(data)
+  }
+}
+
+fn ensure_singleton(appdata: &dyn AppData) -> AppResult<()> {
+  if appdata.kind() != StoreKind::Singleton {
+    return Err(AppError::invalid_argument(format!(
+      "AppData is not a singleton: {}",
+      appdata.id()
+    )));
+  }
+  Ok(())
+}
+
+/// The record of a singleton store, or its default at revision `0` if none was saved.
+pub(super) fn singleton_record(appdata: &dyn AppData) -> AppResult<Record> {
+  let key = key_value(&SingletonKey)?;
+  match appdata.get_record(&key)? {
+    Some(record) => Ok(record),
+    None => Ok(Record {
+      key,
+      data: serde_json::to_vec(&appdata.default_value()?)?,
+      revision: 0,
+    }),
+  }
+}
+
+/// Returns the record of a singleton store, or its default if none was saved.
+#[tauri::command]
+pub async fn appdata_cmd_get_singleton(schema_id: &str) -> AppResult<Record> {
+  let appdata = get_ok(schema_id).await?;
+  ensure_singleton(appdata.as_ref())?;
+  singleton_record(appdata.as_ref())
+}
+
+/// Saves the default record of a singleton store and returns it.
+#[tauri::command]
+pub async fn appdata_cmd_reset_singleton(
+  schema_id: &str,
+  expected_revision: Option<u64>,
+) -> AppResult<Record> {
+  let appdata = get_writable(schema_id).await?;
+  ensure_singleton(appdata.as_ref())?;
+  let data = serde_json::to_vec(&appdata.default_value()?)?;
+  let revision = appdata.save_checked(&data, expected_revision)?;
+  Ok(Record {
+    key: key_value(&SingletonKey)?,
+    data,
+    revision,
+  })
+}
+
+/// Patches the record of a singleton store, starting from the default if none was saved.
+#[tauri::command]
+pub async fn appdata_cmd_update_singleton(
+  schema_id: &str,
+  patch: RecordPatch,
+  expected_revision: Option<u64>,
+) -> AppResult<Record> {
+  let appdata = get_writable(schema_id).await?;
+  ensure_singleton(appdata.as_ref())?;
+  patch_record(
+    appdata.as_ref(),
+    &key_value(&SingletonKey)?,
+    &patch,
+    expected_revision,
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::storage::init_test_storage;
+  use schemars::JsonSchema;
+  use serde::{Deserialize, Serialize};
+  use serde_json::json;
+
+  #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema, crate::AppData)]
+  #[appdata(store = "SingletonTestSettings", singleton)]
+  struct Settings {
+    level: u8,
+  }
+
+  impl Default for Settings {
+    fn default() -> Self {
+      Self { level: 3 }
+    }
+  }
+
+  fn stored() -> Option<Settings> {
+    <Settings as AppEntity>::get_data(&SingletonKey).unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_get_update_reset() {
+    init_test_storage().await;
+    assert_eq!(Settings::get_or_default().unwrap(), Settings::default());
+    // Reading the default writes nothing
+    assert_eq!(stored(), None);
+    let record = appdata_cmd_get_singleton("SingletonTestSettings")
+      .await
+      .unwrap();
+    assert_eq!(record.revision, 0);
+    assert_eq!(stored(), None);
+
+    let updated = Settings::update(|settings| settings.level += 4).unwrap();
+    assert_eq!(updated.level, 7);
+    assert_eq!(stored(), Some(Settings { level: 7 }));
+
+    assert_eq!(Settings::reset().unwrap(), Settings::default());
+    assert_eq!(stored(), Some(Settings::default()));
+
+    Settings::update(|settings| settings.level = 9).unwrap();
+    let record = appdata_cmd_reset_singleton("SingletonTestSettings", None)
+      .await
+      .unwrap();
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(&record.data).unwrap(),
+      json!({"level": 3})
+    );
+    assert_eq!(stored(), Some(Settings::default()));
+    assert_eq!(
+      <Settings as AppEntity>::get_revision(&SingletonKey).unwrap(),
+      record.revision
+    );
+  }
+}
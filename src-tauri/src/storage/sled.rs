@@ -3,6 +3,8 @@ use std::sync::OnceLock;
 
 use reindeer::Db;
 
+use super::AppResult;
+
 static DB: OnceLock<Db> = OnceLock::new();
 
 pub fn init(app_dir: PathBuf) -> anyhow::Result<()> {
@@ -21,7 +23,7 @@ pub(super) fn db() -> &'static Db {
   DB.get().expect("Sled database not initialized")
 }
 
-pub(super) fn flush_db() -> Result<(), String> {
-  let _ = db().flush().map_err(|e| e.to_string())?;
+pub(super) fn flush_db() -> AppResult<()> {
+  let _ = db().flush()?;
   Ok(())
 }
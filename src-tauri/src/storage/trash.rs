@@ -0,0 +1,236 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{
+  db, flush_db, get_ok, get_writable, write_lock, AppError, AppResult, ErrorCode, Record,
+};
+
+const TRASH_PREFIX: &str = "__trash/";
+
+/// A removed record, kept until it is restored or purged
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashEntry {
+  pub key: Value,
+  pub value: Value,
+  /// Schema version `value` is written in
+  pub version: u32,
+  /// Milliseconds since the Unix epoch
+  pub deleted_at: i64,
+}
+
+pub(super) fn trash_tree(store: &str) -> AppResult<::sled::Tree> {
+  Ok(db().open_tree(format!("{}{}", TRASH_PREFIX, store))?)
+}
+
+/// Bytes of the trash entry for the last state of a removed record, stored under its key.
+pub(super) fn trash_entry(key: Value, value: Value, version: u32) -> AppResult<Vec<u8>> {
+  Ok(serde_json::to_vec(&TrashEntry {
+    key,
+    value,
+    version,
+    deleted_at: chrono::Utc::now().timestamp_millis(),
+  })?)
+}
+
+/// Deletes the trash of a store.
+pub(super) fn drop_trash(store: &str) -> AppResult<()> {
+  db().drop_tree(format!("{}{}", TRASH_PREFIX, store))?;
+  Ok(())
+}
+
+/// Removed records of a store, most recently removed first
+pub fn list_trash(store: &str) -> AppResult<Vec<TrashEntry>> {
+  let mut entries = trash_tree(store)?
+    .iter()
+    .values()
+    .map(|bytes| Ok(serde_json::from_slice(&bytes?)?))
+    .collect::<AppResult<Vec<TrashEntry>>>()?;
+  entries.sort_by_key(|entry| std::cmp::Reverse(entry.deleted_at));
+  Ok(entries)
+}
+
+/// Deletes the records removed more than `days` days ago from the trash of every store, returning
+/// how many were deleted. `0` keeps them until the trash is emptied.
+pub fn purge_expired_trash(days: u32) -> AppResult<usize> {
+  if days == 0 {
+    return Ok(0);
+  }
+  let cutoff = chrono::Utc::now().timestamp_millis() - i64::from(days) * 24 * 60 * 60 * 1000;
+  let _guard = write_lock();
+  let mut purged = 0;
+  for name in db().tree_names() {
+    if !name.starts_with(TRASH_PREFIX.as_bytes()) {
+      continue;
+    }
+    let tree = db().open_tree(&name)?;
+    for entry in tree.iter() {
+      let (key, bytes) = entry?;
+      let entry: TrashEntry = serde_json::from_slice(&bytes)?;
+      if entry.deleted_at < cutoff {
+        tree.remove(key)?;
+        purged += 1;
+      }
+    }
+  }
+  flush_db()?;
+  Ok(purged)
+}
+
+/// Lists the removed records of a store, most recently removed first.
+#[tauri::command]
+pub async fn appdata_cmd_list_trash(schema_id: &str) -> AppResult<Vec<TrashEntry>> {
+  list_trash(get_ok(schema_id).await?.id())
+}
+
+/// Saves a removed record again under its key and takes it out of the trash. Fails with
+/// `CONFLICT` if the key has been taken meanwhile.
+#[tauri::command]
+pub async fn appdata_cmd_restore_trash(schema_id: &str, key: Value) -> AppResult<Record> {
+  let appdata = get_writable(schema_id).await?;
+  let key_bytes = appdata.encode_key(&key)?;
+  let tree = trash_tree(appdata.id())?;
+  let entry: TrashEntry = match tree.get(&key_bytes)? {
+    Some(bytes) => serde_json::from_slice(&bytes)?,
+    None => {
+      return Err(AppError::not_found(format!(
+        "Record not in trash: key={}",
+        key
+      )))
+    }
+  };
+  if appdata.exists_data(&key)? {
+    return Err(AppError::new(
+      ErrorCode::Conflict,
+      format!("A record with this key exists: key={}", key),
+    ));
+  }
+  let data = serde_json::to_vec(&appdata.upgrade(entry.version, entry.value)?)?;
+  // Revision 0 fails if the key was taken after the check above
+  let revision = appdata.save_checked(&data, Some(0))?;
+  tree.remove(&key_bytes)?;
+  flush_db()?;
+  Ok(Record {
+    key,
+    data,
+    revision,
+  })
+}
+
+/// Permanently deletes one record from the trash.
+#[tauri::command]
+pub async fn appdata_cmd_purge_trash(schema_id: &str, key: Value) -> AppResult<()> {
+  let appdata = get_writable(schema_id).await?;
+  if trash_tree(appdata.id())?
+    .remove(appdata.encode_key(&key)?)?
+    .is_none()
+  {
+    return Err(AppError::not_found(format!(
+      "Record not in trash: key={}",
+      key
+    )));
+  }
+  flush_db()
+}
+
+/// Permanently deletes every record in the trash of a store and returns how many there were.
+#[tauri::command]
+pub async fn appdata_cmd_empty_trash(schema_id: &str) -> AppResult<usize> {
+  let appdata = get_writable(schema_id).await?;
+  let tree = trash_tree(appdata.id())?;
+  let count = tree.len();
+  tree.clear()?;
+  flush_db()?;
+  Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::storage::{init_test_storage, AppEntity};
+  use reindeer::AsBytes;
+  use schemars::JsonSchema;
+  use serde_json::json;
+
+  const STORE: &str = "TrashTestItem";
+
+  #[derive(
+    Debug, Default, Clone, PartialEq, Serialize, Deserialize, JsonSchema, crate::AppData,
+  )]
+  #[appdata(store = "TrashTestItem", key = id)]
+  struct Item {
+    id: u32,
+    n: u32,
+  }
+
+  fn trashed(id: u32) -> Option<TrashEntry> {
+    list_trash(STORE)
+      .unwrap()
+      .into_iter()
+      .find(|entry| entry.key == json!(id))
+  }
+
+  #[tokio::test]
+  async fn test_remove_and_restore() {
+    init_test_storage().await;
+    Item { id: 1, n: 1 }.save_and_flush().unwrap();
+    Item { id: 2, n: 2 }.save_and_flush().unwrap();
+    Item::remove_and_flush(&1).unwrap();
+    Item::remove_and_flush(&2).unwrap();
+    let entries = list_trash(STORE).unwrap();
+    assert!(entries
+      .windows(2)
+      .all(|pair| pair[0].deleted_at >= pair[1].deleted_at));
+    let entry = trashed(2).unwrap();
+    assert_eq!((entry.value, entry.version), (json!({"id": 2, "n": 2}), 0));
+
+    appdata_cmd_restore_trash(STORE, json!(1)).await.unwrap();
+    assert_eq!(
+      <Item as AppEntity>::get_data(&1).unwrap(),
+      Some(Item { id: 1, n: 1 })
+    );
+    assert_eq!(trashed(1), None);
+    let err = appdata_cmd_restore_trash(STORE, json!(1))
+      .await
+      .unwrap_err();
+    assert_eq!(err.code, ErrorCode::NotFound);
+
+    // The key was taken again since the removal
+    Item { id: 2, n: 3 }.save_and_flush().unwrap();
+    let err = appdata_cmd_restore_trash(STORE, json!(2))
+      .await
+      .unwrap_err();
+    assert_eq!(err.code, ErrorCode::Conflict);
+    assert!(trashed(2).is_some());
+    assert_eq!(
+      <Item as AppEntity>::get_data(&2).unwrap(),
+      Some(Item { id: 2, n: 3 })
+    );
+  }
+
+  #[tokio::test]
+  async fn test_purge_expired() {
+    init_test_storage().await;
+    Item { id: 10, n: 0 }.save_and_flush().unwrap();
+    Item::remove_and_flush(&10).unwrap();
+    let day = 24 * 60 * 60 * 1000;
+    let old = TrashEntry {
+      key: json!(11),
+      value: json!({"id": 11, "n": 0}),
+      version: 0,
+      deleted_at: chrono::Utc::now().timestamp_millis() - 2 * day,
+    };
+    trash_tree(STORE)
+      .unwrap()
+      .insert(11u32.as_bytes(), serde_json::to_vec(&old).unwrap())
+      .unwrap();
+
+    assert_eq!(purge_expired_trash(0).unwrap(), 0);
+    assert!(trashed(11).is_some());
+    assert!(purge_expired_trash(3).is_ok());
+    assert!(trashed(11).is_some());
+    assert!(purge_expired_trash(1).unwrap() >= 1);
+    assert_eq!(trashed(11), None);
+    assert!(trashed(10).is_some());
+  }
+}
@@ -0,0 +1,89 @@
+use jsonschema::Validator;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+
+use super::{AppError, AppResult};
+
+/// A single schema violation, located by the JSON pointer of the offending value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldError {
+  pub path: String,
+  pub message: String,
+}
+
+/// Compiled validators of the typed stores, by store id and schema version
+type ValidatorCache = HashMap<(&'static str, u32), Arc<Validator>>;
+
+static VALIDATORS: LazyLock<Mutex<ValidatorCache>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Compiles `schema` into a validator to check any number of records against.
+pub(super) fn compile_schema(schema: &Value) -> AppResult<Validator> {
+  jsonschema::validator_for(schema)
+    .map_err(|e| AppError::internal(format!("Invalid schema: {}", e)))
+}
+
+/// The validator of a typed store, compiled from `schema` the first time it is needed.
+pub(super) fn store_validator(
+  store: &'static str,
+  version: u32,
+  schema: impl FnOnce() -> schemars::Schema,
+) -> AppResult<Arc<Validator>> {
+  let mut validators = VALIDATORS.lock().unwrap_or_else(|e| e.into_inner());
+  if let Some(validator) = validators.get(&(store, version)) {
+    return Ok(validator.clone());
+  }
+  let validator = Arc::new(compile_schema(schema().as_value())?);
+  validators.insert((store, version), validator.clone());
+  Ok(validator)
+}
+
+/// Checks `instance` with a compiled validator, collecting every violation instead of stopping at
+/// the first one.
+pub(super) fn violations(validator: &Validator, instance: &Value) -> Vec<FieldError> {
+  validator
+    .iter_errors(instance)
+    .map(|e| FieldError {
+      path: e.instance_path.to_string(),
+      message: e.to_string(),
+    })
+    .collect()
+}
+
+/// Validates `instance` against `schema`, collecting every violation instead of stopping at the
+/// first one. Compiles the schema each time; stores keep their validator instead.
+pub fn validate(schema: &schemars::Schema, instance: &Value) -> AppResult<Vec<FieldError>> {
+  Ok(violations(&compile_schema(schema.as_value())?, instance))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::AppConfig;
+  use serde_json::json;
+
+  #[test]
+  fn test_validate_range() {
+    let schema = schemars::schema_for!(AppConfig);
+    let mut config = serde_json::to_value(AppConfig::default()).unwrap();
+    assert_eq!(validate(&schema, &config).unwrap(), vec![]);
+
+    config["features"]["max_concurrent"] = json!(64);
+    let errors = validate(&schema, &config).unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path, "/features/max_concurrent");
+  }
+
+  #[test]
+  fn test_store_validator() {
+    let schema = || schemars::schema_for!(AppConfig);
+    let first = store_validator("ValidateTestConfig", 1, schema).unwrap();
+    let again = store_validator("ValidateTestConfig", 1, || panic!("compiled twice")).unwrap();
+    assert!(Arc::ptr_eq(&first, &again));
+    // A new schema version compiles again
+    let next = store_validator("ValidateTestConfig", 2, schema).unwrap();
+    assert!(!Arc::ptr_eq(&first, &next));
+  }
+}
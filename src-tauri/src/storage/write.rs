@@ -0,0 +1,161 @@
+use ::sled::transaction::{ConflictableTransactionResult, TransactionError};
+use ::sled::Transactional;
+use serde_json::Value;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use super::{
+  flush_db, history_entry, history_keys, history_tree, next_revision, revision_tree, trash_entry,
+  trash_tree, AppError, AppResult, ChangeEvent, ChangeOperation, ErrorCode, HISTORY_LIMIT,
+};
+
+/// A save or removal of one record, in the bytes its store keeps it in
+pub(super) struct RecordWrite {
+  pub store_id: &'static str,
+  pub tree: ::sled::Tree,
+  /// Schema version of the store, for records moved to the trash
+  pub version: u32,
+  pub key: Value,
+  pub key_bytes: Vec<u8>,
+  /// Bytes to store and their JSON form; `None` removes the record
+  pub record: Option<(Vec<u8>, Value)>,
+  /// JSON form of the record before the write, if it existed
+  pub previous: Option<Value>,
+}
+
+/// The trees a write touches, and its writes in order; `None` removes the key.
+#[derive(Default)]
+struct WriteSet {
+  trees: Vec<(String, ::sled::Tree)>,
+  writes: Vec<(usize, Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl WriteSet {
+  /// Index of the tree named `name`, opened with `open` the first time.
+  fn tree(
+    &mut self,
+    name: &str,
+    open: impl FnOnce() -> AppResult<::sled::Tree>,
+  ) -> AppResult<usize> {
+    match self.trees.iter().position(|(n, _)| n == name) {
+      Some(index) => Ok(index),
+      None => {
+        self.trees.push((name.to_owned(), open()?));
+        Ok(self.trees.len() - 1)
+      }
+    }
+  }
+
+  fn write(&mut self, tree: usize, key: Vec<u8>, value: Option<Vec<u8>>) {
+    self.writes.push((tree, key, value));
+  }
+
+  /// Applies every write in one sled transaction.
+  fn commit(self) -> AppResult<()> {
+    if self.writes.is_empty() {
+      // sled cannot run a transaction over no trees
+      return Ok(());
+    }
+    let trees = self
+      .trees
+      .into_iter()
+      .map(|(_, tree)| tree)
+      .collect::<Vec<_>>();
+    trees
+      .as_slice()
+      .transaction(|txs| -> ConflictableTransactionResult<(), ()> {
+        for (tree, key, value) in &self.writes {
+          match value {
+            Some(value) => txs[*tree].insert(key.as_slice(), value.as_slice())?,
+            None => txs[*tree].remove(key.as_slice())?,
+          };
+        }
+        Ok(())
+      })
+      .map_err(|e: TransactionError<()>| {
+        AppError::new(
+          ErrorCode::StorageIo,
+          format!("Write transaction failed: {:?}", e),
+        )
+      })
+  }
+}
+
+/// Writes records together with their revisions, history and trash entries in one transaction,
+/// in order, and flushes once. Returns the events to notify once the write lock is released.
+///
+/// The caller must hold the write lock and have checked the expected revisions.
+pub(super) fn write_records(
+  writes: impl IntoIterator<Item = RecordWrite>,
+) -> AppResult<Vec<ChangeEvent>> {
+  let mut set = WriteSet::default();
+  let mut events = Vec::new();
+  // History keys of each record touched, oldest first, to prune them to `HISTORY_LIMIT`
+  let mut histories: HashMap<(usize, Vec<u8>), Vec<Vec<u8>>> = HashMap::new();
+  for write in writes {
+    let tree = set.tree(write.store_id, || Ok(write.tree.clone()))?;
+    let revisions = set.tree(&format!("__revisions/{}", write.store_id), || {
+      revision_tree(write.store_id)
+    })?;
+    let (operation, revision, value) = match write.record {
+      Some((bytes, value)) => {
+        let revision = next_revision()?;
+        set.write(tree, write.key_bytes.clone(), Some(bytes));
+        set.write(
+          revisions,
+          write.key_bytes.clone(),
+          Some(revision.to_be_bytes().to_vec()),
+        );
+        (ChangeOperation::Save, revision, Some(value))
+      }
+      None => {
+        set.write(tree, write.key_bytes.clone(), None);
+        set.write(revisions, write.key_bytes.clone(), None);
+        (ChangeOperation::Remove, 0, None)
+      }
+    };
+    if let (ChangeOperation::Remove, Some(previous)) = (operation, &write.previous) {
+      let trash = set.tree(&format!("__trash/{}", write.store_id), || {
+        trash_tree(write.store_id)
+      })?;
+      let entry = trash_entry(write.key.clone(), previous.clone(), write.version)?;
+      set.write(trash, write.key_bytes.clone(), Some(entry));
+    }
+    if operation == ChangeOperation::Save || write.previous.is_some() {
+      let history = set.tree(&format!("__history/{}", write.store_id), || {
+        history_tree(write.store_id)
+      })?;
+      let keys = match histories.entry((history, write.key_bytes.clone())) {
+        Entry::Occupied(keys) => keys.into_mut(),
+        Entry::Vacant(keys) => keys.insert(history_keys(&set.trees[history].1, &write.key_bytes)?),
+      };
+      let (history_key, entry) = history_entry(&write.key_bytes, operation, write.previous)?;
+      keys.push(history_key.clone());
+      set.write(history, history_key, Some(entry));
+    }
+    events.push(ChangeEvent {
+      store_id: write.store_id.to_string(),
+      key: Some(write.key),
+      operation,
+      revision: Some(revision),
+      value,
+    });
+  }
+  for ((history, _), keys) in histories {
+    for old in keys.iter().take(keys.len().saturating_sub(HISTORY_LIMIT)) {
+      set.write(history, old.clone(), None);
+    }
+  }
+
+  set.commit()?;
+  flush_db()?;
+  Ok(events)
+}
+
+/// `write_records` for a single record, returning its new revision, `0` once removed.
+pub(super) fn write_record(write: RecordWrite) -> AppResult<(u64, ChangeEvent)> {
+  let event = write_records([write])?
+    .pop()
+    .ok_or_else(|| AppError::internal("Record write produced no event"))?;
+  Ok((event.revision.unwrap_or_default(), event))
+}
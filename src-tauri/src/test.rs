@@ -1,4 +1,4 @@
-use reindeer::Entity;
+use crate::AppData;
 use reindeer::{Deserialize, Serialize};
 use schemars::JsonSchema;
 
@@ -9,8 +9,9 @@ pub fn greet(name: &str) -> String {
 }
 
 // Simple test schema for demonstration
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, AppData)]
 #[schemars(title = "User Profile", example=UserProfile::default())]
+#[appdata(key = id)]
 pub struct UserProfile {
   pub id: u32,
   #[schemars(title = "Full Name", description = "Enter your full name")]
@@ -43,8 +44,9 @@ impl Default for UserProfile {
 }
 
 // Product configuration schema
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, AppData)]
 #[schemars(title = "Product Config", example=ProductConfig::default())]
+#[appdata(key = id)]
 pub struct ProductConfig {
   /// The product ID
   pub id: u32,
@@ -78,8 +80,9 @@ impl Default for ProductConfig {
 }
 
 // System settings schema
-#[derive(Debug, Serialize, Deserialize, JsonSchema, Default)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Default, AppData)]
 #[schemars(title = "System Settings")]
+#[appdata(key = id)]
 pub struct SystemSettings {
   pub id: u32,
   #[schemars(title = "Theme", description = "Select the application theme")]
@@ -99,51 +102,3 @@ pub struct SystemSettings {
   )]
   pub max_file_size: u32,
 }
-
-impl Entity for ProductConfig {
-  type Key = u32;
-
-  fn store_name() -> &'static str {
-    "ProductConfig"
-  }
-
-  fn get_key(&self) -> &Self::Key {
-    &self.id
-  }
-
-  fn set_key(&mut self, key: &Self::Key) {
-    self.id = *key;
-  }
-}
-
-impl Entity for UserProfile {
-  type Key = u32;
-
-  fn store_name() -> &'static str {
-    "UserProfile"
-  }
-
-  fn get_key(&self) -> &Self::Key {
-    &self.id
-  }
-
-  fn set_key(&mut self, key: &Self::Key) {
-    self.id = *key;
-  }
-}
-
-impl Entity for SystemSettings {
-  type Key = u32;
-
-  fn store_name() -> &'static str {
-    "SystemSettings"
-  }
-
-  fn get_key(&self) -> &Self::Key {
-    &self.id
-  }
-
-  fn set_key(&mut self, key: &Self::Key) {
-    self.id = *key;
-  }
-}
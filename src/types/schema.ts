@@ -3,6 +3,8 @@
  * Based on https://json-schema.org/draft/2020-12/schema
  */
 
+import type { AppDataKeyType } from "@/utils/tauri-commands";
+
 // Core schema types
 export type JSONSchemaType =
   | "object"
@@ -107,6 +109,9 @@ export interface JSONSchema {
 
 // Extended schema for our application
 export interface AppSchema extends JSONSchema {
+  // Key type of the store, added by the backend
+  "x-key-type"?: AppDataKeyType;
+
   // Custom extensions for our app
   ui?: {
     component?: string;
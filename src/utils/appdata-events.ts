@@ -0,0 +1,48 @@
+/**
+ * AppData change events
+ * Subscribe to mutations of a store, or of a single record, emitted by the backend
+ */
+
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
+import type { AppDataKey } from "./tauri-commands";
+
+export const APPDATA_CHANGE_EVENT = "appdata://change";
+
+export type ChangeOperation = "save" | "remove" | "import";
+
+export interface ChangeEvent {
+  storeId: string;
+  key: AppDataKey | null;
+  operation: ChangeOperation;
+  revision: number | null;
+  value: Record<string, unknown> | null;
+}
+
+/**
+ * Render a key as an event name segment, matching the backend's `key_segment`
+ */
+export const keySegment = (key: AppDataKey): string => {
+  const raw = Array.isArray(key) ? key.map(keySegment).join(":") : `${key}`;
+  return raw.replace(/[^A-Za-z0-9_:-]/g, "_");
+};
+
+/**
+ * Listen to changes of a store, or of one record when `key` is given
+ */
+export const listenAppDataChange = (
+  storeId: string,
+  key: AppDataKey | null,
+  handler: (event: ChangeEvent) => void
+): Promise<UnlistenFn> => {
+  const name =
+    key === null
+      ? `${APPDATA_CHANGE_EVENT}/${storeId}`
+      : `${APPDATA_CHANGE_EVENT}/${storeId}/${keySegment(key)}`;
+  const expected = key === null ? null : JSON.stringify(key);
+  return listen<ChangeEvent>(name, (event) => {
+    // Different keys may share a segment, so compare the key itself
+    if (expected === null || JSON.stringify(event.payload.key) === expected) {
+      handler(event.payload);
+    }
+  });
+};
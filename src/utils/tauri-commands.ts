@@ -6,12 +6,43 @@
 export const TAURI_COMMANDS = {
   // AppData commands (used by both appdata and config modes)
   APPDATA: {
+    STORES: "appdata_cmd_stores",
+    DYNAMIC_STORES: "appdata_cmd_dynamic_stores",
+    DEFINE_STORE: "appdata_cmd_define_store",
+    DROP_STORE: "appdata_cmd_drop_store",
     GET_SCHEMA: "appdata_cmd_get_schema",
     GET_DATA: "appdata_cmd_get_data",
+    GET_SINGLETON: "appdata_cmd_get_singleton",
+    UPDATE_SINGLETON: "appdata_cmd_update_singleton",
+    RESET_SINGLETON: "appdata_cmd_reset_singleton",
     SAVE_DATA: "appdata_cmd_save_data",
+    NEW_DATA: "appdata_cmd_new_data",
+    CREATE_DATA: "appdata_cmd_create_data",
+    PATCH_DATA: "appdata_cmd_patch_data",
+    BULK_UPDATE: "appdata_cmd_bulk_update",
+    VALIDATE_DATA: "appdata_cmd_validate_data",
     REMOVE_DATA: "appdata_cmd_remove_data",
     EXISTS_DATA: "appdata_cmd_exists_data",
     FIND_NEXT_AVAILABLE_KEY: "appdata_cmd_find_next_available_key",
+    LIST_DATA: "appdata_cmd_list_data",
+    QUERY_DATA: "appdata_cmd_query_data",
+    BATCH: "appdata_cmd_batch",
+    LIST_REVISIONS: "appdata_cmd_list_revisions",
+    GET_REVISION: "appdata_cmd_get_revision",
+    RESTORE_REVISION: "appdata_cmd_restore_revision",
+    EXPORT: "appdata_cmd_export",
+    IMPORT: "appdata_cmd_import",
+    PREVIEW_IMPORT: "appdata_cmd_preview_import",
+    DATA_FORMATS: "appdata_cmd_data_formats",
+    EXPORT_DATA: "appdata_cmd_export_data",
+    IMPORT_DATA: "appdata_cmd_import_data",
+    PROPOSE_CSV_MAPPING: "appdata_cmd_propose_csv_mapping",
+    IMPORT_CSV: "appdata_cmd_import_csv",
+    LIST_TRASH: "appdata_cmd_list_trash",
+    RESTORE_TRASH: "appdata_cmd_restore_trash",
+    PURGE_TRASH: "appdata_cmd_purge_trash",
+    EMPTY_TRASH: "appdata_cmd_empty_trash",
+    DIFF_DATA: "appdata_cmd_diff_data",
   },
   // Config commands
   CONFIG: {
@@ -22,3 +53,247 @@ export const TAURI_COMMANDS = {
     GREET: "greet",
   },
 } as const;
+
+/**
+ * Record key as sent to appdata commands; composite keys are arrays
+ */
+export type AppDataKey = number | string | AppDataKey[];
+
+/**
+ * Shape of the keys of a store, published as `x-key-type` in its schema
+ */
+export type AppDataKeyType =
+  | { kind: "integer" }
+  | { kind: "string" }
+  | { kind: "uuid" }
+  | { kind: "composite"; parts: AppDataKeyType[] };
+
+/**
+ * Key type of a store schema, defaulting to integer keys
+ */
+export const getKeyType = (schema: {
+  "x-key-type"?: AppDataKeyType;
+}): AppDataKeyType => schema["x-key-type"] ?? { kind: "integer" };
+
+/**
+ * Record returned by `appdata_cmd_get_data` and `appdata_cmd_create_data`; singleton records
+ * (`appdata_cmd_get_singleton`) have key `0` and revision `0` until first saved, as do the
+ * unsaved defaults returned by `appdata_cmd_new_data`
+ */
+export interface AppDataRecord {
+  key: AppDataKey;
+  data: number[];
+  /** Pass back as `expectedRevision` when saving or removing */
+  revision: number;
+}
+
+/**
+ * Built-in formats of `appdata_cmd_export_data` and `appdata_cmd_import_data`; more may be
+ * registered by the backend, see `appdata_cmd_data_formats`
+ */
+export type DataFormat = "json" | "ndjson" | "csv" | "toml" | "yaml";
+
+/**
+ * A registered store, as described by `appdata_cmd_stores`
+ */
+export interface StoreDescriptor {
+  id: string;
+  /** Schema title, or the id if the schema has none */
+  title: string;
+  description?: string;
+  kind: "singleton" | "collection";
+  /** Record fields that make up the key, in key order */
+  keyFields: string[];
+  keyType: AppDataKeyType;
+  count: number;
+  /** Write commands reject read-only stores */
+  readOnly: boolean;
+}
+
+/**
+ * A store defined at runtime by a JSON Schema, passed as `definition` to
+ * `appdata_cmd_define_store`
+ */
+export interface DynamicStoreDef {
+  /** ASCII letters, digits, `-` and `_` */
+  id: string;
+  /** JSON Schema every record must match */
+  schema: Record<string, unknown>;
+  /** Record field holding the key; defaults to `id` */
+  keyField?: string;
+  /** Defaults to integer keys; composite keys are not supported */
+  keyType?: Exclude<AppDataKeyType, { kind: "composite" }>;
+}
+
+/**
+ * Partial update for `appdata_cmd_patch_data`: an RFC 6902 JSON Patch or an RFC 7386
+ * Merge Patch, applied to the stored record
+ */
+export type RecordPatch =
+  | {
+      kind: "json";
+      patch: {
+        op: "add" | "remove" | "replace" | "move" | "copy" | "test";
+        path: string;
+        from?: string;
+        value?: unknown;
+      }[];
+    }
+  | { kind: "merge"; patch: Record<string, unknown> };
+
+/**
+ * Result of `appdata_cmd_bulk_update`
+ */
+export interface BulkUpdateResult {
+  /** Number of records matching the filter */
+  matched: number;
+  /** Keys of the records the patch changed */
+  keys: AppDataKey[];
+  /** `false` for a dry run */
+  applied: boolean;
+}
+
+/**
+ * What `appdata_cmd_import` does with a record whose key is already taken
+ */
+export type MergeStrategy = "skip" | "overwrite" | "renumber" | "fail";
+
+export interface ImportRecordReport {
+  storeId: string;
+  /** Position of the record in the archived store */
+  index: number;
+  key: AppDataKey | null;
+  action:
+    | "created"
+    | "overwritten"
+    | "skipped"
+    | "renumbered"
+    | "conflict"
+    | "invalid";
+  newKey?: AppDataKey;
+  message?: string;
+}
+
+/**
+ * Report returned by `appdata_cmd_import`; nothing was written unless `applied`
+ */
+export interface ImportReport {
+  applied: boolean;
+  records: ImportRecordReport[];
+}
+
+/**
+ * A single schema violation, located by the JSON pointer of the offending value
+ */
+export interface FieldError {
+  path: string;
+  message: string;
+}
+
+/**
+ * A value that differs between two versions of a record
+ */
+export interface FieldChange {
+  /** JSON pointer of the value */
+  path: string;
+  kind: "added" | "removed" | "changed";
+  /** The path spelled with schema titles; absent for the whole record */
+  label?: string;
+  /** Absent if the value was added */
+  before?: unknown;
+  /** Absent if the value was removed */
+  after?: unknown;
+}
+
+/**
+ * One side of `appdata_cmd_diff_data`: a stored record, one of its revisions, or an unsaved payload
+ */
+export type DiffSide =
+  | { kind: "record"; key: AppDataKey }
+  | { kind: "revision"; key: AppDataKey; revisionId: number }
+  | { kind: "payload"; data: number[] };
+
+export interface PreviewRecord {
+  /** Position of the record in the file */
+  index: number;
+  key: AppDataKey | null;
+  status: "new" | "changed" | "unchanged" | "invalid";
+  changes?: FieldChange[];
+  errors?: FieldError[];
+}
+
+/**
+ * Result of `appdata_cmd_preview_import`; nothing is written
+ */
+export interface ImportPreview {
+  new: number;
+  changed: number;
+  unchanged: number;
+  invalid: number;
+  records: PreviewRecord[];
+}
+
+export interface ColumnMapping {
+  column: string;
+  /** Dotted property path, e.g. `features.dark_mode`; `null` skips the column */
+  property: string | null;
+}
+
+/**
+ * Mapping proposed by `appdata_cmd_propose_csv_mapping`, to be reviewed before
+ * passing its `columns` to `appdata_cmd_import_csv`
+ */
+export interface CsvMapping {
+  columns: ColumnMapping[];
+  /** Properties no column maps to */
+  unmapped: string[];
+}
+
+export interface CsvImportReport {
+  imported: number;
+  /** Rows that were not saved, by line in the file (the header is line 1) */
+  failed: { line: number; errors: FieldError[] }[];
+}
+
+/**
+ * A removed record, as listed by `appdata_cmd_list_trash`
+ */
+export interface TrashEntry {
+  key: AppDataKey;
+  /** The record as it was when removed */
+  value: Record<string, unknown>;
+  /** Schema version of `value`; restoring upgrades it to the current one */
+  version: number;
+  /** Milliseconds since the Unix epoch */
+  deletedAt: number;
+}
+
+/**
+ * Structured error returned by appdata and config commands
+ */
+export interface AppDataError {
+  code:
+    | "NOT_FOUND"
+    | "VALIDATION_FAILED"
+    | "DECODE_ERROR"
+    | "STORAGE_IO"
+    | "INVALID_ARGUMENT"
+    | "CONFLICT"
+    | "INTERNAL";
+  message: string;
+  path?: string;
+  fields?: FieldError[];
+  sources?: string[];
+}
+
+export const isAppDataError = (err: unknown): err is AppDataError =>
+  typeof err === "object" &&
+  err !== null &&
+  typeof (err as AppDataError).code === "string" &&
+  typeof (err as AppDataError).message === "string";
+
+/**
+ * Format an error thrown by `invoke` for display
+ */
+export const formatCommandError = (err: unknown): string =>
+  isAppDataError(err) ? err.message : String(err);
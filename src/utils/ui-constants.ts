@@ -22,7 +22,7 @@ export const UI_MESSAGES = {
     CANNOT_LOAD_DATA: "Cannot load data: missing schema or key",
     SAVE_CONFIRMATION: "Are you sure you want to save the current data?",
     DELETE_CONFIRMATION:
-      "Are you sure you want to delete this data? This action cannot be undone.",
+      "Are you sure you want to delete this data? It will be moved to the trash, where it can be restored.",
     RELOAD_CONFIRMATION:
       "Are you sure you want to reload data from the server? This will discard any unsaved changes.",
     RESET_CONFIRMATION:
@@ -34,7 +34,7 @@ export const UI_MESSAGES = {
     DATA_SAVED: "Data saved successfully",
     DATA_UPDATED: "Data updated successfully",
     NEW_DATA_CREATED: "New data created successfully",
-    DATA_DELETED: "Data deleted successfully",
+    DATA_DELETED: "Data moved to trash",
     DATA_RELOADED: "Data reloaded successfully",
   },
 